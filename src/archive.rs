@@ -0,0 +1,186 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{Error, Result, ResultExt};
+use git2::{ErrorCode, Repository};
+
+use crate::subprocess;
+use crate::MergedOrGone;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ArchiveKind {
+    /// refs/archive/<date>/<branch>
+    Ref,
+    /// An annotated tag archive/<date>/<branch>
+    Tag,
+}
+
+impl FromStr for ArchiveKind {
+    type Err = ArchiveKindParseError;
+
+    fn from_str(arg: &str) -> Result<ArchiveKind, Self::Err> {
+        match arg.trim() {
+            "ref" => Ok(ArchiveKind::Ref),
+            "tag" => Ok(ArchiveKind::Tag),
+            _ => Err(ArchiveKindParseError {
+                message: format!("Unexpected archive: {}", arg),
+            }),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ArchiveKindParseError {
+    message: String,
+}
+
+impl Display for ArchiveKindParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ArchiveKindParseError: {}", &self.message)
+    }
+}
+
+impl std::error::Error for ArchiveKindParseError {}
+
+/// Keeps the tips of the branches before they are deleted.
+pub struct Archive {
+    pub kind: ArchiveKind,
+    /// YYYY-MM-DD in UTC
+    pub date: String,
+    /// The remote that the archives are pushed to.
+    pub remote: Option<String>,
+}
+
+/// A ref to archive and where it is archived to.
+#[derive(Eq, PartialEq, Debug)]
+pub struct ArchiveEntry {
+    pub refname: String,
+    pub archive_refname: String,
+}
+
+impl Archive {
+    pub fn new(kind: ArchiveKind, remote: Option<String>) -> Result<Archive> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        Ok(Archive {
+            kind,
+            date: date_from_unix_time(now),
+            remote,
+        })
+    }
+
+    /// Every local branch and remote ref that is going to be deleted.
+    pub fn plan(&self, to_delete: &MergedOrGone) -> Vec<ArchiveEntry> {
+        let locals = to_delete
+            .locals()
+            .into_iter()
+            .map(|branch| format!("refs/heads/{}", branch));
+        let remotes = to_delete
+            .remotes()
+            .into_iter()
+            .chain(to_delete.local_only_remotes())
+            .chain(to_delete.orphans())
+            .map(str::to_string);
+        let mut result: Vec<_> = locals
+            .chain(remotes)
+            .map(|refname| ArchiveEntry {
+                archive_refname: self.archive_refname(&refname),
+                refname,
+            })
+            .collect();
+        result.sort_by(|a, b| a.refname.cmp(&b.refname));
+        result
+    }
+
+    /// refs/heads/feature -> refs/archive/<date>/feature
+    /// refs/remotes/origin/feature -> refs/archive/<date>/origin/feature
+    fn archive_refname(&self, refname: &str) -> String {
+        let name = ["refs/heads/", "refs/remotes/"]
+            .iter()
+            .find(|prefix| refname.starts_with(*prefix))
+            .map(|prefix| &refname[prefix.len()..])
+            .unwrap_or(refname);
+        match self.kind {
+            ArchiveKind::Ref => format!("refs/archive/{}/{}", self.date, name),
+            ArchiveKind::Tag => format!("refs/tags/archive/{}/{}", self.date, name),
+        }
+    }
+
+    pub fn print_plan(&self, plan: &[ArchiveEntry]) {
+        if plan.is_empty() {
+            return;
+        }
+        match &self.remote {
+            Some(remote) => message!("Archive before delete, and push them to {}:", remote),
+            None => message!("Archive before delete:"),
+        }
+        for entry in plan {
+            message!("  - {} -> {}", entry.refname, entry.archive_refname);
+        }
+    }
+
+    /// An archive that already points to the same commit is left as it is.
+    pub fn archive(&self, repo: &Repository, plan: &[ArchiveEntry], dry_run: bool) -> Result<()> {
+        let mut archived = Vec::new();
+        for entry in plan {
+            let commit = repo.find_reference(&entry.refname)?.peel_to_commit()?;
+            match repo.find_reference(&entry.archive_refname) {
+                Ok(existing) => {
+                    let existing = existing.peel_to_commit()?;
+                    if existing.id() != commit.id() {
+                        return Err(Error::message(format!(
+                            "The archive {} already exists for another commit",
+                            entry.archive_refname
+                        )));
+                    }
+                }
+                Err(err) if err.code() == ErrorCode::NotFound => {
+                    let oid = commit.id().to_string();
+                    match self.kind {
+                        ArchiveKind::Ref => subprocess::update_ref_create(
+                            repo,
+                            &entry.archive_refname,
+                            &oid,
+                            dry_run,
+                        )?,
+                        ArchiveKind::Tag => {
+                            let tag_name = entry.archive_refname.trim_start_matches("refs/tags/");
+                            let message = format!("Archived {} by git-trim", entry.refname);
+                            subprocess::tag_annotated(repo, tag_name, &oid, &message, dry_run)?
+                        }
+                    }
+                }
+                Err(err) => return Err(err.into()),
+            }
+            archived.push(entry.archive_refname.as_str());
+        }
+
+        if let Some(remote) = &self.remote {
+            if !archived.is_empty() {
+                subprocess::push_refs(repo, remote, &archived, dry_run)
+                    .with_context(|| format!("Failed to push the archives to {}", remote))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// YYYY-MM-DD of the unix time in UTC.
+/// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn date_from_unix_time(secs: u64) -> String {
+    let days = (secs / 86400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
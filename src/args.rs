@@ -3,9 +3,12 @@ use std::fmt::{Debug, Display, Formatter};
 use std::hash::Hash;
 use std::iter::FromIterator;
 use std::mem::discriminant;
-use std::process::exit;
+use std::path::PathBuf;
 use std::str::FromStr;
 
+use crate::archive::ArchiveKind;
+use crate::forge::ForgeKind;
+
 #[derive(Hash, Eq, PartialEq, Clone, Debug)]
 pub enum Scope {
     All,
@@ -18,6 +21,13 @@ pub enum FilterUnit {
     MergedRemote(Scope),
     GoneLocal,
     GoneRemote(Scope),
+    StaleLocal,
+    StaleRemote(Scope),
+    MergedRemoteLocal(Scope),
+    GoneRemoteLocal(Scope),
+    StaleRemoteLocal(Scope),
+    Orphaned,
+    GoneUnpushedLocal,
 }
 
 #[derive(Debug, Clone)]
@@ -36,6 +46,9 @@ impl DeleteFilter {
             MergedRemote(Scope::All),
             GoneLocal,
             GoneRemote(Scope::All),
+            StaleLocal,
+            StaleRemote(Scope::All),
+            Orphaned,
         ])
     }
 
@@ -73,6 +86,73 @@ impl DeleteFilter {
         false
     }
 
+    pub fn filter_stale_local(&self) -> bool {
+        self.0.contains(&FilterUnit::StaleLocal)
+    }
+
+    pub fn filter_stale_remote(&self, remote: &str) -> bool {
+        for filter in self.0.iter() {
+            match filter {
+                FilterUnit::StaleRemote(Scope::All) => return true,
+                FilterUnit::StaleRemote(Scope::Scoped(specific)) if specific == remote => {
+                    return true
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// Delete the merged remote ref only from the local repository, without pushing.
+    pub fn filter_merged_remote_local(&self, remote: &str) -> bool {
+        for filter in self.0.iter() {
+            match filter {
+                FilterUnit::MergedRemoteLocal(Scope::All) => return true,
+                FilterUnit::MergedRemoteLocal(Scope::Scoped(specific)) if specific == remote => {
+                    return true
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// Delete the gone remote ref only from the local repository, without pushing.
+    pub fn filter_gone_remote_local(&self, remote: &str) -> bool {
+        for filter in self.0.iter() {
+            match filter {
+                FilterUnit::GoneRemoteLocal(Scope::All) => return true,
+                FilterUnit::GoneRemoteLocal(Scope::Scoped(specific)) if specific == remote => {
+                    return true
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// Delete the stale remote ref only from the local repository, without pushing.
+    pub fn filter_stale_remote_local(&self, remote: &str) -> bool {
+        for filter in self.0.iter() {
+            match filter {
+                FilterUnit::StaleRemoteLocal(Scope::All) => return true,
+                FilterUnit::StaleRemoteLocal(Scope::Scoped(specific)) if specific == remote => {
+                    return true
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+
+    pub fn filter_orphaned(&self) -> bool {
+        self.0.contains(&FilterUnit::Orphaned)
+    }
+
+    pub fn filter_gone_unpushed_local(&self) -> bool {
+        self.0.contains(&FilterUnit::GoneUnpushedLocal)
+    }
+
     pub fn into_option(self) -> Option<Self> {
         if self.0.is_empty() {
             None
@@ -92,28 +172,57 @@ impl FromStr for DeleteFilter {
         for arg in args.split(',') {
             let some_pair: Vec<_> = arg.splitn(2, ':').map(str::trim).collect();
             let filters = match *some_pair.as_slice() {
-                ["all"] => vec![MergedLocal, MergedRemote(All), GoneLocal, GoneRemote(All)],
+                ["all"] => vec![
+                    MergedLocal,
+                    MergedRemote(All),
+                    GoneLocal,
+                    GoneRemote(All),
+                    StaleLocal,
+                    StaleRemote(All),
+                    Orphaned,
+                ],
                 ["all", remote] => vec![
                     MergedLocal,
                     MergedRemote(Scoped(remote.to_string())),
                     GoneLocal,
                     GoneRemote(Scoped(remote.to_string())),
+                    StaleLocal,
+                    StaleRemote(Scoped(remote.to_string())),
                 ],
                 ["merged"] => vec![MergedLocal, MergedRemote(All)],
                 ["merged", remote] => vec![MergedLocal, MergedRemote(Scoped(remote.to_string()))],
                 ["gone"] => vec![GoneLocal, GoneRemote(All)],
                 ["gone", remote] => vec![GoneLocal, GoneRemote(Scoped(remote.to_string()))],
-                ["local"] => vec![MergedLocal, GoneLocal],
-                ["remote"] => vec![MergedRemote(All), GoneRemote(All)],
+                ["stale"] => vec![StaleLocal, StaleRemote(All)],
+                ["stale", remote] => vec![StaleLocal, StaleRemote(Scoped(remote.to_string()))],
+                ["local"] => vec![MergedLocal, GoneLocal, StaleLocal],
+                ["remote"] => vec![MergedRemote(All), GoneRemote(All), StaleRemote(All)],
                 ["remote", remote] => vec![
                     MergedRemote(Scoped(remote.to_string())),
                     GoneRemote(Scoped(remote.to_string())),
+                    StaleRemote(Scoped(remote.to_string())),
                 ],
                 ["merged-local"] => vec![MergedLocal],
                 ["merged-remote"] => vec![MergedRemote(All)],
                 ["merged-remote", remote] => vec![MergedRemote(Scoped(remote.to_string()))],
                 ["gone-local"] => vec![GoneLocal],
+                ["gone-remote"] => vec![GoneRemote(All)],
                 ["gone-remote", remote] => vec![GoneRemote(Scoped(remote.to_string()))],
+                ["stale-local"] => vec![StaleLocal],
+                ["stale-remote"] => vec![StaleRemote(All)],
+                ["stale-remote", remote] => vec![StaleRemote(Scoped(remote.to_string()))],
+                ["merged-remote-local"] => vec![MergedRemoteLocal(All)],
+                ["merged-remote-local", remote] => {
+                    vec![MergedRemoteLocal(Scoped(remote.to_string()))]
+                }
+                ["gone-remote-local"] => vec![GoneRemoteLocal(All)],
+                ["gone-remote-local", remote] => vec![GoneRemoteLocal(Scoped(remote.to_string()))],
+                ["stale-remote-local"] => vec![StaleRemoteLocal(All)],
+                ["stale-remote-local", remote] => {
+                    vec![StaleRemoteLocal(Scoped(remote.to_string()))]
+                }
+                ["orphaned"] => vec![Orphaned],
+                ["gone-unpushed-local"] => vec![GoneLocal, GoneUnpushedLocal],
                 _ if arg.is_empty() => vec![],
                 _ => {
                     return Err(DeleteFilterParseError {
@@ -139,10 +248,15 @@ impl FromIterator<FilterUnit> for DeleteFilter {
         let mut result = HashSet::new();
         for filter in iter.into_iter() {
             match filter {
-                MergedLocal | GoneLocal => {
+                MergedLocal | GoneLocal | StaleLocal | Orphaned | GoneUnpushedLocal => {
                     result.insert(filter.clone());
                 }
-                MergedRemote(All) | GoneRemote(All) => {
+                MergedRemote(All)
+                | GoneRemote(All)
+                | StaleRemote(All)
+                | MergedRemoteLocal(All)
+                | GoneRemoteLocal(All)
+                | StaleRemoteLocal(All) => {
                     result.retain(|x| discriminant(x) != discriminant(&filter));
                     result.insert(filter.clone());
                 }
@@ -156,6 +270,26 @@ impl FromIterator<FilterUnit> for DeleteFilter {
                         result.insert(filter.clone());
                     }
                 }
+                StaleRemote(_) => {
+                    if !result.contains(&StaleRemote(All)) {
+                        result.insert(filter.clone());
+                    }
+                }
+                MergedRemoteLocal(_) => {
+                    if !result.contains(&MergedRemoteLocal(All)) {
+                        result.insert(filter.clone());
+                    }
+                }
+                GoneRemoteLocal(_) => {
+                    if !result.contains(&GoneRemoteLocal(All)) {
+                        result.insert(filter.clone());
+                    }
+                }
+                StaleRemoteLocal(_) => {
+                    if !result.contains(&StaleRemoteLocal(All)) {
+                        result.insert(filter.clone());
+                    }
+                }
             }
         }
 
@@ -187,6 +321,39 @@ impl Display for DeleteFilterParseError {
 
 impl std::error::Error for DeleteFilterParseError {}
 
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum OutputFormat {
+    Human,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = OutputFormatParseError;
+
+    fn from_str(arg: &str) -> Result<OutputFormat, Self::Err> {
+        match arg.trim() {
+            "human" => Ok(OutputFormat::Human),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(OutputFormatParseError {
+                message: format!("Unexpected output format: {}", arg),
+            }),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct OutputFormatParseError {
+    message: String,
+}
+
+impl Display for OutputFormatParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "OutputFormatParseError: {}", &self.message)
+    }
+}
+
+impl std::error::Error for OutputFormatParseError {}
+
 #[derive(derive_deref::Deref, Debug, Clone, Default)]
 pub struct CommaSeparatedSet<T>(Vec<T>);
 
@@ -250,6 +417,8 @@ pub struct Args {
     pub bases: Vec<CommaSeparatedSet<String>>,
 
     // Comma separated or a multiple arguments of glob pattern of branches that never be deleted.
+    // '!<pattern>' excludes, 'regex:<regex>' matches by a regex and 'remote:<remote>/<glob>' matches
+    // only the remote refs of the remote.
     #[structopt(short, long)]
     pub protected: Vec<CommaSeparatedSet<String>>,
 
@@ -259,6 +428,10 @@ pub struct Args {
     #[structopt(long, hidden(true))]
     pub update: bool,
 
+    /// Select branches to delete from a checklist instead of confirming the whole plan
+    #[structopt(long)]
+    pub interactive: bool,
+
     /// Do not ask confirm [config: trim.confirm]
     #[structopt(long)]
     pub no_confirm: bool,
@@ -272,56 +445,166 @@ pub struct Args {
     pub detach: bool,
 
     /// Comma separated values of '<filter unit>[:<remote name>]'.
-    /// Filter unit is one of the 'all, merged, gone, local, remote, merged-local, merged-remote, gone-local, gone-remote'.
-    /// 'all' implies 'merged-local,merged-remote,gone-local,gone-remote'.
+    /// Filter unit is one of the 'all, merged, gone, stale, local, remote, merged-local, merged-remote, gone-local, gone-remote, stale-local, stale-remote, merged-remote-local, gone-remote-local, stale-remote-local, orphaned, gone-unpushed-local'.
+    /// 'all' implies 'merged-local,merged-remote,gone-local,gone-remote,stale-local,stale-remote,orphaned'.
+    /// 'orphaned' is remote refs whose remote is removed or renamed. They are deleted only locally.
     /// 'merged' implies 'merged-local,merged-remote'.
     /// 'gone' implies 'gone-local,gone-remote'.
-    /// 'local' implies 'merged-local,gone-local'.
-    /// 'remote' implies 'merged-remote,gone-remote'.
+    /// 'stale' implies 'stale-local,stale-remote'.
+    /// 'local' implies 'merged-local,gone-local,stale-local'.
+    /// 'remote' implies 'merged-remote,gone-remote,stale-remote'.
+    /// 'merged-remote-local', 'gone-remote-local' and 'stale-remote-local' delete the remote refs
+    /// only from the local repository without pushing. They take precedence over the ones without '-local'.
+    /// 'gone-unpushed-local' implies 'gone-local', and also deletes gone local branches with commits
+    /// that are on neither a remote nor a base. They are kept back unless it is given explicitly.
     ///
     /// You can scope a filter unit to specific remote ':<remote name>' to a 'filter unit'
-    /// if the filter unit implies 'merged-remote' or 'gone-remote'.
+    /// if the filter unit implies 'merged-remote', 'gone-remote' or 'stale-remote'.
     /// If there are filter units that is scoped, it trims merged or gone remote branches in the specified remote branch.
     /// If there are any filter unit that isn't scoped, it trims all merged or gone remote branches.
     /// [default : 'merged'] [config: trim.filter]
     #[structopt(short, long)]
     pub delete: Vec<DeleteFilter>,
 
+    /// Query the pull request states from the forge, one of 'github, gitlab, gitea'.
+    /// The access token is read from the environment variable GIT_TRIM_FORGE_TOKEN. [config: trim.forge]
+    #[structopt(long)]
+    pub forge: Option<ForgeKind>,
+
+    /// The API url of the forge. e.g. https://github.example.com/api/v3 [config: trim.forgeUrl]
+    #[structopt(long, value_name = "url")]
+    pub forge_url: Option<String>,
+
+    /// Days after which a branch is stale if it is neither merged nor updated.
+    /// Stale branches are not detected if it is not set. [config: trim.staleAfter]
+    #[structopt(long, value_name = "days")]
+    pub stale_after: Option<u64>,
+
+    /// Archive the branches before deleting them, one of 'ref, tag'.
+    /// 'ref' creates 'refs/archive/<date>/<branch>', and 'tag' creates an annotated tag
+    /// 'archive/<date>/<branch>'. Remote refs are archived as '<remote>/<branch>'. [config: trim.archive]
+    #[structopt(long)]
+    pub archive: Option<ArchiveKind>,
+
+    /// Push the archives to the remote before deleting the branches. [config: trim.archiveRemote]
+    #[structopt(long, value_name = "remote")]
+    pub archive_remote: Option<String>,
+
     #[structopt(long)]
     pub dry_run: bool,
+
+    /// Print the plan, and exit with 3 if there are branches to trim without deleting anything.
+    /// It is for CI jobs that check the repository hygiene.
+    #[structopt(long)]
+    pub check: bool,
+
+    /// Explain why the branch is trimmed or not, and exit without deleting anything.
+    /// It shows the upstream resolution, the merge tests against each base, the protection,
+    /// the filter and the decision of the branch.
+    #[structopt(long, value_name = "branch")]
+    pub explain: Option<String>,
+
+    /// Format of the summary. One of the 'human, json'.
+    /// 'json' prints every local branch and remote ref with its classification,
+    /// the reason, the base it is compared against and its upstreams.
+    /// [default: human]
+    #[structopt(long, default_value = "human")]
+    pub format: OutputFormat,
+
+    /// Trim every repository under the directory instead of the current repository.
+    /// The repositories are planned in parallel, and their summaries are printed together
+    /// before a single confirmation. A failure of a repository doesn't stop the others.
+    #[structopt(
+        long,
+        value_name = "dir",
+        parse(from_os_str),
+        conflicts_with_all = &["repos", "explain", "undo"]
+    )]
+    pub recursive: Option<PathBuf>,
+
+    /// Trim the repositories that are listed in the file, one path per line, like '--recursive'.
+    /// Relative paths are relative to the directory of the file.
+    #[structopt(
+        long,
+        value_name = "file",
+        parse(from_os_str),
+        conflicts_with_all = &["explain", "undo"]
+    )]
+    pub repos: Option<PathBuf>,
+
+    /// Confirm each repository instead of all at once with '--recursive' or '--repos'
+    #[structopt(long)]
+    pub confirm_each_repo: bool,
+
+    /// Trim the initialized submodules together with the repository.
+    /// A submodule reads 'trim.*' from its own config, and then from the superproject's. [config: trim.recurseSubmodules]
+    #[structopt(long)]
+    pub recurse_submodules: bool,
+    #[structopt(long, hidden(true))]
+    pub no_recurse_submodules: bool,
+
+    /// Restore the branches that are deleted by the run of the given id, or by the latest run.
+    /// Every run that deletes branches records them in '$GIT_DIR/trim/journal/<run id>.json'.
+    #[structopt(long, value_name = "run id")]
+    pub undo: Option<Option<String>>,
+
+    /// Push the deleted remote branches again on '--undo'
+    #[structopt(long)]
+    pub undo_push: bool,
 }
 
 impl Args {
-    pub fn update(&self) -> Option<bool> {
+    pub fn update(&self) -> Result<Option<bool>, ExclusiveFlagsError> {
         exclusive_bool(("update", self.update), ("no-update", self.no_update))
     }
 
-    pub fn confirm(&self) -> Option<bool> {
+    pub fn confirm(&self) -> Result<Option<bool>, ExclusiveFlagsError> {
         exclusive_bool(("confirm", self.confirm), ("no-confirm", self.no_confirm))
     }
 
-    pub fn detach(&self) -> Option<bool> {
+    pub fn detach(&self) -> Result<Option<bool>, ExclusiveFlagsError> {
         exclusive_bool(("detach", self.detach), ("no-detach", self.no_detach))
     }
+
+    pub fn recurse_submodules(&self) -> Result<Option<bool>, ExclusiveFlagsError> {
+        exclusive_bool(
+            ("recurse-submodules", self.recurse_submodules),
+            ("no-recurse-submodules", self.no_recurse_submodules),
+        )
+    }
 }
 
 fn exclusive_bool(
     (name_pos, value_pos): (&str, bool),
     (name_neg, value_neg): (&str, bool),
-) -> Option<bool> {
+) -> Result<Option<bool>, ExclusiveFlagsError> {
     if value_pos && value_neg {
-        eprintln!(
-            "Error: Flag '{}' and '{}' cannot be used simultaneously",
-            name_pos, name_neg,
-        );
-        exit(-1);
+        return Err(ExclusiveFlagsError {
+            message: format!(
+                "Flag '{}' and '{}' cannot be used simultaneously",
+                name_pos, name_neg,
+            ),
+        });
     }
 
     if value_pos {
-        Some(true)
+        Ok(Some(true))
     } else if value_neg {
-        Some(false)
+        Ok(Some(false))
     } else {
-        None
+        Ok(None)
     }
 }
+
+#[derive(Debug)]
+pub struct ExclusiveFlagsError {
+    message: String,
+}
+
+impl Display for ExclusiveFlagsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", &self.message)
+    }
+}
+
+impl std::error::Error for ExclusiveFlagsError {}
@@ -0,0 +1,110 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Result, ResultExt};
+use git2::{Config, ConfigLevel, Repository};
+use log::*;
+
+/// Repositories under the directory, including itself.
+/// It doesn't look into a repository for nested ones, since they are usually submodules or vendored.
+/// Directories under it that can't be read are skipped with a warning.
+pub fn discover_repositories(root: &Path) -> Result<Vec<PathBuf>> {
+    let mut result = Vec::new();
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        if is_repository(&dir) {
+            trace!("Found a repository: {:?}", dir);
+            result.push(dir);
+            continue;
+        }
+        // An unreadable directory shouldn't stop the others from being trimmed.
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if dir == root => {
+                return Err(err).with_context(|| format!("Failed to read {:?}", dir));
+            }
+            Err(err) => {
+                warn!("Skip: failed to read {:?}: {}", dir, err);
+                continue;
+            }
+        };
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    warn!("Skip: failed to read an entry of {:?}: {}", dir, err);
+                    continue;
+                }
+            };
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => stack.push(entry.path()),
+                Ok(_) => {}
+                Err(err) => warn!("Skip: failed to read {:?}: {}", entry.path(), err),
+            }
+        }
+    }
+    result.sort();
+    Ok(result)
+}
+
+/// Repositories that are listed in the file, one path per line.
+/// Empty lines and lines that start with '#' are ignored.
+/// Relative paths are relative to the directory of the file.
+pub fn read_repository_list(file: &Path) -> Result<Vec<PathBuf>> {
+    let content = fs::read_to_string(file).with_context(|| format!("Failed to read {:?}", file))?;
+    let base = file.parent().unwrap_or_else(|| Path::new(""));
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| base.join(line))
+        .collect())
+}
+
+/// Working trees of the initialized submodules of the repository, including nested ones,
+/// with the working tree of their immediate superproject.
+/// Submodules that are not initialized are skipped since there is nothing to trim.
+pub fn discover_submodules(repo: &Repository) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let superproject = match repo.workdir() {
+        Some(workdir) => workdir.to_path_buf(),
+        None => return Ok(Vec::new()),
+    };
+    let mut result = Vec::new();
+    for submodule in repo.submodules()? {
+        let submodule_repo = match submodule.open() {
+            Ok(submodule_repo) => submodule_repo,
+            Err(err) => {
+                debug!(
+                    "Skip: the submodule is not initialized: {:?}: {}",
+                    submodule.path(),
+                    err
+                );
+                continue;
+            }
+        };
+        if let Some(workdir) = submodule_repo.workdir() {
+            result.push((workdir.to_path_buf(), superproject.clone()));
+        }
+        result.extend(discover_submodules(&submodule_repo)?);
+    }
+    Ok(result)
+}
+
+/// The config to read `trim.*` of a submodule from, which is only its own `.git/config`.
+/// The keys that are not set there are read from the config of the superproject as a fallback,
+/// so the global and the system config don't win over the superproject's.
+pub fn submodule_config(submodule: &Repository) -> Result<Config> {
+    Ok(submodule
+        .config()?
+        .open_level(ConfigLevel::Local)?
+        .snapshot()?)
+}
+
+/// A working tree with `.git`, or a bare repository like `<name>.git`.
+fn is_repository(dir: &Path) -> bool {
+    if dir.join(".git").exists() {
+        return true;
+    }
+    dir.extension() == Some(OsStr::new("git")) && Repository::open_bare(dir).is_ok()
+}
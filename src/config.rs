@@ -2,7 +2,7 @@ use std::iter::FromIterator;
 use std::ops::Deref;
 use std::str::FromStr;
 
-use anyhow::Result;
+use crate::error::{Error, Result};
 use git2::{Config, ErrorClass, ErrorCode};
 use log::*;
 
@@ -43,6 +43,7 @@ pub struct ConfigBuilder<'a, T> {
     config: &'a Config,
     key: &'a str,
     explicit: Option<(&'a str, T)>,
+    fallback: Option<&'a Config>,
     default: Option<&'a T>,
 }
 
@@ -51,6 +52,7 @@ pub fn get<'a, T>(config: &'a Config, key: &'a str) -> ConfigBuilder<'a, T> {
         config,
         key,
         explicit: None,
+        fallback: None,
         default: None,
     }
 }
@@ -70,6 +72,15 @@ where
         }
     }
 
+    /// The config to read the key from if the config doesn't have it at all.
+    /// Unlike the levels of a config, the values of a multi-valued key are not merged with it.
+    pub fn with_fallback(self, config: Option<&'a Config>) -> ConfigBuilder<'a, T> {
+        ConfigBuilder {
+            fallback: config,
+            ..self
+        }
+    }
+
     pub fn with_default(self, value: &'a T) -> ConfigBuilder<'a, T> {
         ConfigBuilder {
             default: Some(value),
@@ -78,6 +89,12 @@ where
     }
 }
 
+impl<'a, T> ConfigBuilder<'a, T> {
+    fn configs(&self) -> impl Iterator<Item = &'a Config> {
+        std::iter::once(self.config).chain(self.fallback)
+    }
+}
+
 impl<'a, T> ConfigBuilder<'a, T>
 where
     T: ConfigValues + Clone,
@@ -89,20 +106,21 @@ where
                 source: source.to_string(),
             }));
         }
-        match T::get_config_value(self.config, self.key) {
-            Ok(value) => Ok(Some(ConfigValue::Explicit {
-                value,
-                source: self.key.to_string(),
-            })),
-            Err(err) if config_not_exist(&err) => {
-                if let Some(default) = self.default {
-                    Ok(Some(ConfigValue::Implicit(default.clone())))
-                } else {
-                    Ok(None)
+        for config in self.configs() {
+            match T::get_config_value(config, self.key) {
+                Ok(value) => {
+                    return Ok(Some(ConfigValue::Explicit {
+                        value,
+                        source: self.key.to_string(),
+                    }))
                 }
+                Err(err) if config_not_exist(&err) => continue,
+                Err(err) => return Err(err),
             }
-            Err(err) => Err(err),
         }
+        Ok(self
+            .default
+            .map(|default| ConfigValue::Implicit(default.clone())))
     }
 }
 
@@ -121,21 +139,21 @@ where
             }));
         }
 
-        let result = match self.config.get_str(self.key) {
-            Ok(value) => Some(ConfigValue::Explicit {
-                value: parse(value)?,
-                source: self.key.to_string(),
-            }),
-            Err(err) if config_not_exist(&err) => {
-                if let Some(default) = self.default {
-                    Some(ConfigValue::Implicit(default.clone()))
-                } else {
-                    None
+        for config in self.configs() {
+            match config.get_str(self.key) {
+                Ok(value) => {
+                    return Ok(Some(ConfigValue::Explicit {
+                        value: parse(value)?,
+                        source: self.key.to_string(),
+                    }))
                 }
+                Err(err) if config_not_exist(&err) => continue,
+                Err(err) => return Err(err.into()),
             }
-            Err(err) => return Err(err.into()),
-        };
-        Ok(result)
+        }
+        Ok(self
+            .default
+            .map(|default| ConfigValue::Implicit(default.clone())))
     }
 
     pub fn parse_multi_with<F>(self, parse: F) -> Result<Option<ConfigValue<T>>>
@@ -149,21 +167,18 @@ where
             }));
         }
 
-        let result = match Vec::<String>::get_config_value(self.config, self.key) {
-            Ok(values) if !values.is_empty() => Some(ConfigValue::Explicit {
-                value: parse(&values)?,
-                source: self.key.to_string(),
-            }),
-            Ok(_) => {
-                if let Some(default) = self.default {
-                    Some(ConfigValue::Implicit(default.clone()))
-                } else {
-                    None
-                }
+        for config in self.configs() {
+            let values = Vec::<String>::get_config_value(config, self.key)?;
+            if !values.is_empty() {
+                return Ok(Some(ConfigValue::Explicit {
+                    value: parse(&values)?,
+                    source: self.key.to_string(),
+                }));
             }
-            Err(err) => return Err(err.into()),
-        };
-        Ok(result)
+        }
+        Ok(self
+            .default
+            .map(|default| ConfigValue::Implicit(default.clone())))
     }
 }
 
@@ -173,7 +188,8 @@ impl<'a, T> ConfigBuilder<'a, T> {
         T: FromStr + Clone,
         T::Err: std::error::Error + Send + Sync + 'static,
     {
-        self.parse_with(|str| Ok(str.parse()?))
+        let key = self.key;
+        self.parse_with(|str| str.parse().map_err(|err| Error::config(key, err)))
     }
 
     pub fn parse_flatten<U>(self) -> Result<Option<ConfigValue<T>>>
@@ -181,10 +197,15 @@ impl<'a, T> ConfigBuilder<'a, T> {
         T: FromStr + IntoIterator<Item = U> + FromIterator<U> + Clone,
         T::Err: std::error::Error + Send + Sync + 'static,
     {
+        let key = self.key;
         self.parse_multi_with(|strings| {
             let mut result = Vec::new();
             for x in strings {
-                result.push(T::from_str(x)?.into_iter())
+                result.push(
+                    T::from_str(x)
+                        .map_err(|err| Error::config(key, err))?
+                        .into_iter(),
+                )
             }
             Ok(T::from_iter(result.into_iter().flatten()))
         })
@@ -247,6 +268,12 @@ pub fn get_push_remote(config: &Config, branch: &str) -> Result<ConfigValue<Stri
     get_remote(config, branch)
 }
 
+pub fn get_remote_url(config: &Config, remote_name: &str) -> Result<Option<String>> {
+    Ok(get(config, &format!("remote.{}.url", remote_name))
+        .read()?
+        .map(ConfigValue::unwrap))
+}
+
 pub fn get_remote(config: &Config, branch: &str) -> Result<ConfigValue<String>> {
     Ok(get(config, &format!("branch.{}.remote", branch))
         .with_default(&String::from("origin"))
@@ -0,0 +1,96 @@
+use std::fmt::{Display, Formatter};
+
+use crate::error::{Error, Result};
+
+/// Which refs are deleted, and which are left behind with why.
+/// Local branches are named as they are, and remote refs are named with their full refnames.
+#[derive(Default, Debug)]
+pub struct DeletionReport {
+    pub deleted: Vec<String>,
+    pub failed: Vec<DeletionFailure>,
+    /// Refs that are not deleted on purpose, e.g. they are changed on remote since fetch.
+    pub kept_back: Vec<DeletionFailure>,
+}
+
+#[derive(Eq, PartialEq, Clone, Debug)]
+pub struct DeletionFailure {
+    pub refname: String,
+    /// The error message including the captured stderr of git.
+    pub message: String,
+}
+
+impl DeletionReport {
+    pub fn record(&mut self, refname: &str, result: Result<()>) {
+        match result {
+            Ok(()) => self.deleted.push(refname.to_string()),
+            Err(Error::ChangedOnRemote(_)) => self.kept_back.push(DeletionFailure {
+                refname: refname.to_string(),
+                message: "changed on remote since fetch".to_string(),
+            }),
+            Err(err) => self.failed.push(DeletionFailure {
+                refname: refname.to_string(),
+                message: err.to_string(),
+            }),
+        }
+    }
+
+    pub fn extend(&mut self, other: DeletionReport) {
+        self.deleted.extend(other.deleted);
+        self.failed.extend(other.failed);
+        self.kept_back.extend(other.kept_back);
+    }
+
+    pub fn print(&self) {
+        if !self.deleted.is_empty() {
+            message!("Deleted:");
+            for refname in &self.deleted {
+                message!("  - {}", refname);
+            }
+        }
+        if !self.kept_back.is_empty() {
+            message!("Kept back:");
+            for kept_back in &self.kept_back {
+                message!("  - {}: {}", kept_back.refname, kept_back.message);
+            }
+        }
+        if !self.failed.is_empty() {
+            message!("Failed to delete:");
+            for failure in &self.failed {
+                message!("  - {}", failure.refname);
+                for line in failure.message.lines() {
+                    message!("      {}", line);
+                }
+            }
+        }
+    }
+
+    pub fn into_result(self) -> Result<(), DeletionError> {
+        if self.failed.is_empty() {
+            Ok(())
+        } else {
+            Err(DeletionError {
+                failed: self.failed,
+            })
+        }
+    }
+}
+
+/// Some refs are left behind while the others are deleted.
+#[derive(Debug)]
+pub struct DeletionError {
+    pub failed: Vec<DeletionFailure>,
+}
+
+impl Display for DeletionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let refnames: Vec<_> = self.failed.iter().map(|x| x.refname.as_str()).collect();
+        write!(
+            f,
+            "Failed to delete {} ref(s): {}",
+            self.failed.len(),
+            refnames.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for DeletionError {}
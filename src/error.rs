@@ -0,0 +1,164 @@
+use std::fmt::{Display, Formatter};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Errors of the library API.
+#[derive(Debug)]
+pub enum Error {
+    Git(git2::Error),
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    /// An invalid value of a git config, or of the option that overrides it.
+    Config {
+        key: String,
+        message: String,
+    },
+    UnsupportedRefspec(String),
+    /// A git subprocess exited with failure.
+    Subprocess {
+        command: String,
+        status: String,
+        stderr: String,
+    },
+    /// What is not UTF-8, e.g. a branch name.
+    NonUtf8(String),
+    /// The remote ref doesn't have a remote that fetches it.
+    MissingRemote(String),
+    /// The ref on the remote has moved since the last fetch, so it is kept.
+    ChangedOnRemote(String),
+    Forge(String),
+    /// Anything else that is specific to the operation, e.g. there is nothing to undo.
+    Message(String),
+    /// What it was doing when the error happened.
+    Context {
+        context: String,
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    pub fn config(key: &str, message: impl Display) -> Error {
+        Error::Config {
+            key: key.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn non_utf8(what: &str) -> Error {
+        Error::NonUtf8(what.to_string())
+    }
+
+    pub fn message(message: impl Display) -> Error {
+        Error::Message(message.to_string())
+    }
+
+    /// Is it an invalid config, even under the contexts?
+    pub fn is_config(&self) -> bool {
+        match self {
+            Error::Config { .. } => true,
+            Error::Context { source, .. } => source.is_config(),
+            _ => false,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Git(err) => write!(f, "{}", err),
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Json(err) => write!(f, "{}", err),
+            Error::Config { key, message } => write!(f, "Invalid config {}: {}", key, message),
+            Error::UnsupportedRefspec(refspec) => write!(f, "Unsupported refspec: {}", refspec),
+            Error::Subprocess {
+                command,
+                status,
+                stderr,
+            } if stderr.is_empty() => write!(f, "`{}` failed with {}", command, status),
+            Error::Subprocess {
+                command,
+                status,
+                stderr,
+            } => write!(f, "`{}` failed with {}: {}", command, status, stderr),
+            Error::NonUtf8(what) => write!(f, "non utf-8 {}", what),
+            Error::MissingRemote(remote_ref) => {
+                write!(f, "{} is an orphaned remote ref", remote_ref)
+            }
+            Error::ChangedOnRemote(refname) => {
+                write!(f, "{} is changed on remote since fetch", refname)
+            }
+            Error::Forge(message) => write!(f, "{}", message),
+            Error::Message(message) => write!(f, "{}", message),
+            Error::Context { context, source } => write!(f, "{}: {}", context, source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Git(err) => Some(err),
+            Error::Io(err) => Some(err),
+            Error::Json(err) => Some(err),
+            Error::Context { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<git2::Error> for Error {
+    fn from(err: git2::Error) -> Self {
+        Error::Git(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Json(err)
+    }
+}
+
+impl From<std::str::Utf8Error> for Error {
+    fn from(_: std::str::Utf8Error) -> Self {
+        Error::non_utf8("output of git")
+    }
+}
+
+impl From<std::fmt::Error> for Error {
+    fn from(err: std::fmt::Error) -> Self {
+        Error::message(err)
+    }
+}
+
+impl From<std::time::SystemTimeError> for Error {
+    fn from(err: std::time::SystemTimeError) -> Self {
+        Error::message(err)
+    }
+}
+
+pub(crate) trait ResultExt<T> {
+    fn with_context<F>(self, context: F) -> Result<T>
+    where
+        F: FnOnce() -> String;
+}
+
+impl<T, E> ResultExt<T> for std::result::Result<T, E>
+where
+    E: Into<Error>,
+{
+    fn with_context<F>(self, context: F) -> Result<T>
+    where
+        F: FnOnce() -> String,
+    {
+        self.map_err(|err| Error::Context {
+            context: context(),
+            source: Box::new(err.into()),
+        })
+    }
+}
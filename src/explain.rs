@@ -0,0 +1,360 @@
+use std::fmt::{Display, Write};
+
+use crate::error::{Error, Result, ResultExt};
+use git2::{BranchType, Config as GitConfig};
+
+use crate::config::{self, ConfigValue};
+use crate::native::{self, MergedBy};
+use crate::remote_ref::{
+    get_fetch_remote_ref, get_push_ref_on_remote, get_push_remote_ref,
+    get_ref_on_remote_from_remote_ref, RefOnRemote,
+};
+use crate::subprocess;
+use crate::{
+    get_marked_protection, get_merged_or_gone, resolve_base_refs, resolve_base_remote_refs,
+    resolve_protected_pattern, resolve_protected_refs, Config, Git, MergedOrGone,
+};
+
+/// Why a branch is trimmed or not, step by step.
+/// It is what `RUST_LOG=trace` tells about the branch, but in a readable form.
+pub fn explain(git: &Git, config: &Config, branch_name: &str) -> Result<String> {
+    let branch = git
+        .repo
+        .find_branch(branch_name, BranchType::Local)
+        .with_context(|| format!("There is no local branch {}", branch_name))?;
+    let refname = branch
+        .get()
+        .name()
+        .ok_or_else(|| Error::non_utf8("branch ref"))?
+        .to_string();
+
+    let mut out = String::new();
+    writeln!(out, "Branch {} ({})", branch_name, refname)?;
+
+    writeln!(out, "Upstream:")?;
+    let remote = config::get_remote(&git.config, branch_name)?;
+    writeln!(out, "  remote: {}", describe(&remote))?;
+    let merge_key = format!("branch.{}.merge", branch_name);
+    let merge: Option<ConfigValue<String>> = config::get(&git.config, &merge_key).read()?;
+    writeln!(out, "  merge: {}", describe_option(merge.as_ref()))?;
+    let fetch = get_fetch_remote_ref(&git.repo, &git.config, branch_name)?;
+    writeln!(
+        out,
+        "  fetch upstream: {}",
+        fetch.as_deref().unwrap_or("none")
+    )?;
+
+    writeln!(out, "Push:")?;
+    let push_remote = config::get_push_remote(&git.config, branch_name)?;
+    writeln!(out, "  push remote: {}", describe(&push_remote))?;
+    let push_refspecs = get_push_refspecs(&git.config, &push_remote)?;
+    if push_refspecs.is_empty() {
+        let push_default = config::get(&git.config, "push.default")
+            .with_default(&String::from("simple"))
+            .read()?
+            .expect("has default");
+        writeln!(out, "  push.default: {}", describe(&push_default))?;
+    } else {
+        writeln!(
+            out,
+            "  push refspecs: {} (from remote.{}.push)",
+            push_refspecs.join(", "),
+            *push_remote
+        )?;
+    }
+    match get_push_ref_on_remote(&git.repo, &git.config, branch_name)? {
+        Some(RefOnRemote {
+            remote_name,
+            refname,
+        }) => writeln!(out, "  push destination: {} on {}", refname, remote_name)?,
+        None => writeln!(out, "  push destination: none")?,
+    }
+    let push = get_push_remote_ref(&git.repo, &git.config, branch_name)?;
+    writeln!(
+        out,
+        "  push upstream: {}",
+        push.as_deref().unwrap_or("none")
+    )?;
+
+    writeln!(out, "Merge tests:")?;
+    let base_remote_refs = resolve_base_remote_refs(&git.repo, &git.config, &config.bases)?;
+    let result = get_merged_or_gone(git, config)?;
+    let skipped = get_skip_reason(
+        git,
+        config,
+        branch_name,
+        fetch.as_deref(),
+        &base_remote_refs,
+    )?;
+    for base in &base_remote_refs {
+        writeln!(out, "  against {}:", base)?;
+        let noff =
+            subprocess::get_noff_merged_locals(&git.repo, &git.config, std::slice::from_ref(base))?;
+        writeln!(
+            out,
+            "    git branch --merged: {}",
+            merged_or_not(noff.contains(branch_name))
+        )?;
+        // The same test as the classification, including the fallback to the git subprocess.
+        let patches = match native::merged_by(&git.repo, base, branch_name) {
+            Ok(Some(MergedBy::CherryPick)) => "merged by cherry-pick".to_string(),
+            Ok(Some(MergedBy::Squash)) => "merged by squash".to_string(),
+            Ok(None) => "not merged".to_string(),
+            Err(err) => format!(
+                "{} (by the git subprocess, since {})",
+                merged_or_not(subprocess::is_merged(&git.repo, base, branch_name)?),
+                err
+            ),
+        };
+        writeln!(out, "    patches: {}", patches)?;
+        for c in result
+            .classifications
+            .iter()
+            .filter(|c| c.branch_name == branch_name && &c.base == base)
+        {
+            if config.forge.is_some() {
+                let state = c
+                    .pull_request
+                    .map(|state| format!("{:?}", state).to_lowercase());
+                writeln!(
+                    out,
+                    "    pull request: {}",
+                    state.as_deref().unwrap_or("none")
+                )?;
+            }
+            if config.stale_after.is_some() {
+                writeln!(out, "    stale: {}", yes_or_no(c.branch_is_stale))?;
+            }
+            writeln!(out, "    classified: {}", c.message)?;
+        }
+        if let Some(skipped) = skipped {
+            writeln!(out, "    classified: skip: {}", skipped)?;
+        }
+    }
+
+    writeln!(out, "Protection:")?;
+    let base_refs = resolve_base_refs(&git.repo, &git.config, &config.bases)?;
+    let mut related = vec![branch_name, refname.as_str()];
+    related.extend(fetch.as_deref());
+    related.extend(
+        push.as_deref()
+            .filter(|push| Some(*push) != fetch.as_deref()),
+    );
+    let bases: Vec<_> = related.iter().filter(|x| base_refs.contains(**x)).collect();
+    if bases.is_empty() {
+        writeln!(out, "  base: no")?;
+    } else {
+        writeln!(out, "  base: {}", join(&bases))?;
+    }
+    let mut patterns: Vec<_> = config.protected_branches.iter().collect();
+    patterns.sort();
+    for pattern in patterns {
+        let refs =
+            resolve_protected_pattern(&git.repo, &git.config, pattern.trim_start_matches('!'))?;
+        let matched: Vec<_> = related.iter().filter(|x| refs.contains(**x)).collect();
+        let how = if pattern.starts_with('!') {
+            "excludes"
+        } else {
+            "protects"
+        };
+        if matched.is_empty() {
+            writeln!(out, "  pattern '{}': no match", pattern)?;
+        } else {
+            writeln!(out, "  pattern '{}': {} {}", pattern, how, join(&matched))?;
+        }
+    }
+    match get_marked_protection(&git.repo, &git.config, branch_name)? {
+        Some(why) => writeln!(out, "  marked: protected {}", why)?,
+        None => writeln!(out, "  marked: no")?,
+    }
+
+    writeln!(out, "Filter:")?;
+    let mut classified = MergedOrGone::default();
+    for c in result
+        .classifications
+        .iter()
+        .filter(|c| c.branch_name == branch_name)
+    {
+        classified = classified.accumulate(c.result.clone());
+    }
+    classified.remove_stale_if_merged_or_gone();
+    let local_filters = [
+        (
+            "merged local",
+            &classified.merged_locals,
+            config.filter.filter_merged_local(),
+        ),
+        (
+            "gone local",
+            &classified.gone_locals,
+            config.filter.filter_gone_local(),
+        ),
+        (
+            "stale local",
+            &classified.stale_locals,
+            config.filter.filter_stale_local(),
+        ),
+    ];
+    let mut any = false;
+    for (label, branches, selected) in local_filters.iter() {
+        if branches.contains(branch_name) {
+            any = true;
+            writeln!(
+                out,
+                "  {} {}: {}",
+                label,
+                branch_name,
+                selected_or_not(*selected)
+            )?;
+        }
+    }
+    let remote_categories = [
+        ("merged remote", &classified.merged_remotes),
+        ("gone remote", &classified.gone_remotes),
+        ("stale remote", &classified.stale_remotes),
+    ];
+    for (label, remote_refs) in remote_categories.iter() {
+        let mut remote_refs: Vec<_> = remote_refs.iter().collect();
+        remote_refs.sort();
+        for remote_ref in remote_refs {
+            any = true;
+            let remote_name = match get_ref_on_remote_from_remote_ref(&git.repo, remote_ref)? {
+                Some(ref_on_remote) => ref_on_remote.remote_name,
+                None => {
+                    writeln!(out, "  {} {}: orphaned", label, remote_ref)?;
+                    continue;
+                }
+            };
+            let (selected, selected_locally) = match *label {
+                "merged remote" => (
+                    config.filter.filter_merged_remote(&remote_name),
+                    config.filter.filter_merged_remote_local(&remote_name),
+                ),
+                "gone remote" => (
+                    config.filter.filter_gone_remote(&remote_name),
+                    config.filter.filter_gone_remote_local(&remote_name),
+                ),
+                _ => (
+                    config.filter.filter_stale_remote(&remote_name),
+                    config.filter.filter_stale_remote_local(&remote_name),
+                ),
+            };
+            let how = if selected_locally {
+                "selected to delete only from the local repository"
+            } else {
+                selected_or_not(selected)
+            };
+            writeln!(out, "  {} {}: {}", label, remote_ref, how)?;
+        }
+    }
+    if !any {
+        writeln!(out, "  nothing to filter")?;
+    }
+
+    writeln!(out, "Decision:")?;
+    let local_decision = if result.to_delete.locals().contains(&branch_name) {
+        "delete".to_string()
+    } else if let Some(why) = result.kept_back.get(branch_name) {
+        format!("keep: {}", why)
+    } else if let Some(why) = result.kept_back.get(&refname) {
+        format!("keep: {}", why)
+    } else {
+        "keep".to_string()
+    };
+    writeln!(out, "  {}: {}", branch_name, local_decision)?;
+    let mut remote_refs: Vec<_> = fetch.iter().chain(push.iter()).collect();
+    remote_refs.sort();
+    remote_refs.dedup();
+    for remote_ref in remote_refs {
+        let decision = if result.to_delete.remotes().contains(&remote_ref.as_str()) {
+            "delete on the remote".to_string()
+        } else if result
+            .to_delete
+            .local_only_remotes()
+            .contains(&remote_ref.as_str())
+        {
+            "delete only from the local repository".to_string()
+        } else if let Some(why) = result.kept_back.get(remote_ref) {
+            format!("keep: {}", why)
+        } else {
+            "keep".to_string()
+        };
+        writeln!(out, "  {}: {}", remote_ref, decision)?;
+    }
+
+    Ok(out)
+}
+
+/// Same as the conditions that `get_merged_or_gone` skips the branch without classification.
+fn get_skip_reason(
+    git: &Git,
+    config: &Config,
+    branch_name: &str,
+    fetch: Option<&str>,
+    base_remote_refs: &[String],
+) -> Result<Option<&'static str>> {
+    if config::get_remote(&git.config, branch_name)?.is_implicit() {
+        return Ok(Some("the branch doesn't have a tracking remote"));
+    }
+    let protected_refs =
+        resolve_protected_refs(&git.repo, &git.config, &config.protected_branches)?;
+    if protected_refs.contains(branch_name) {
+        return Ok(Some("the branch is protected"));
+    }
+    if let Some(fetch) = fetch {
+        if base_remote_refs.iter().any(|x| x == fetch) {
+            return Ok(Some("the branch is the base"));
+        }
+    }
+    let branch = git.repo.find_branch(branch_name, BranchType::Local)?;
+    if branch.get().symbolic_target().is_some() {
+        return Ok(Some("the branch is a symbolic ref"));
+    }
+    Ok(None)
+}
+
+fn get_push_refspecs(config: &GitConfig, remote_name: &str) -> Result<Vec<String>> {
+    Ok(config::get(config, &format!("remote.{}.push", remote_name))
+        .read()?
+        .map(ConfigValue::unwrap)
+        .unwrap_or_default())
+}
+
+fn describe<T: Display>(value: &ConfigValue<T>) -> String {
+    match value {
+        ConfigValue::Explicit { value, source } => format!("{} (from {})", value, source),
+        ConfigValue::Implicit(value) => format!("{} (default)", value),
+    }
+}
+
+fn describe_option<T: Display>(value: Option<&ConfigValue<T>>) -> String {
+    value.map(describe).unwrap_or_else(|| "none".to_string())
+}
+
+fn join(values: &[&&str]) -> String {
+    values.iter().map(|x| **x).collect::<Vec<_>>().join(", ")
+}
+
+fn merged_or_not(merged: bool) -> &'static str {
+    if merged {
+        "merged"
+    } else {
+        "not merged"
+    }
+}
+
+fn yes_or_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+fn selected_or_not(selected: bool) -> &'static str {
+    if selected {
+        "selected"
+    } else {
+        "filtered out"
+    }
+}
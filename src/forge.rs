@@ -0,0 +1,392 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use crate::error::{Error, Result};
+use log::*;
+use serde::Serialize;
+use serde_json::Value;
+
+/// The environment variable for the access token of the forge API.
+pub const TOKEN_ENV: &str = "GIT_TRIM_FORGE_TOKEN";
+
+#[derive(Serialize, Eq, PartialEq, Debug, Copy, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub enum PullRequestState {
+    Open,
+    Merged,
+    /// Closed without merge
+    Closed,
+}
+
+/// A code hosting service that knows the pull requests (or merge requests) of the branches.
+pub trait Forge: Send + Sync {
+    /// The state of the pull request from the `branch` of the `head_url` repository
+    /// to the `base_url` repository. It is `None` if there is no pull request.
+    /// Only the pull requests whose head commit is `head_sha` are considered,
+    /// since the branch name might be reused after an old pull request is merged or closed.
+    /// If there are many of them, an open one wins over a merged one, and a merged one wins
+    /// over a closed one.
+    fn pull_request_state(
+        &self,
+        base_url: &str,
+        head_url: &str,
+        branch: &str,
+        head_sha: &str,
+    ) -> Result<Option<PullRequestState>>;
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ForgeKind {
+    GitHub,
+    GitLab,
+    Gitea,
+}
+
+impl FromStr for ForgeKind {
+    type Err = ForgeKindParseError;
+
+    fn from_str(arg: &str) -> Result<ForgeKind, Self::Err> {
+        match arg.trim() {
+            "github" => Ok(ForgeKind::GitHub),
+            "gitlab" => Ok(ForgeKind::GitLab),
+            "gitea" => Ok(ForgeKind::Gitea),
+            _ => Err(ForgeKindParseError {
+                message: format!("Unexpected forge: {}", arg),
+            }),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ForgeKindParseError {
+    message: String,
+}
+
+impl Display for ForgeKindParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ForgeKindParseError: {}", &self.message)
+    }
+}
+
+impl std::error::Error for ForgeKindParseError {}
+
+/// `api_url` is required for Gitea since there is no well-known public instance of it.
+pub fn new_forge(
+    kind: ForgeKind,
+    api_url: Option<&str>,
+    token: Option<String>,
+) -> Result<Box<dyn Forge>> {
+    let forge: Box<dyn Forge> = match kind {
+        ForgeKind::GitHub => Box::new(GitHub {
+            api_url: api_url.unwrap_or("https://api.github.com").to_string(),
+            token,
+        }),
+        ForgeKind::GitLab => Box::new(GitLab {
+            api_url: api_url.unwrap_or("https://gitlab.com/api/v4").to_string(),
+            token,
+        }),
+        ForgeKind::Gitea => Box::new(Gitea::new(
+            api_url
+                .ok_or_else(|| Error::config("trim.forgeUrl", "It is required for Gitea"))?
+                .to_string(),
+            token,
+        )),
+    };
+    Ok(forge)
+}
+
+/// It also works for GitHub Enterprise with `https://<host>/api/v3`.
+pub struct GitHub {
+    pub api_url: String,
+    pub token: Option<String>,
+}
+
+impl Forge for GitHub {
+    fn pull_request_state(
+        &self,
+        base_url: &str,
+        head_url: &str,
+        branch: &str,
+        head_sha: &str,
+    ) -> Result<Option<PullRequestState>> {
+        let base = repository_path(base_url);
+        let head = repository_path(head_url);
+        let head_owner = head.split('/').next().unwrap_or_default();
+        let url = format!(
+            "{}/repos/{}/pulls?state=all&head={}",
+            self.api_url.trim_end_matches('/'),
+            base,
+            percent_encode(&format!("{}:{}", head_owner, branch)),
+        );
+        let header = self
+            .token
+            .as_ref()
+            .map(|x| format!("Authorization: token {}", x));
+        let pulls = http_get_json_pages(&url, header.as_deref())?;
+        pick_state(&pulls, "/head/sha", head_sha, |pull| {
+            match (pull["state"].as_str(), pull["merged_at"].is_null()) {
+                (Some("open"), _) => Some(PullRequestState::Open),
+                (Some("closed"), false) => Some(PullRequestState::Merged),
+                (Some("closed"), true) => Some(PullRequestState::Closed),
+                _ => None,
+            }
+        })
+    }
+}
+
+/// It also works for self-managed GitLab with `https://<host>/api/v4`.
+pub struct GitLab {
+    pub api_url: String,
+    pub token: Option<String>,
+}
+
+impl Forge for GitLab {
+    fn pull_request_state(
+        &self,
+        base_url: &str,
+        _head_url: &str,
+        branch: &str,
+        head_sha: &str,
+    ) -> Result<Option<PullRequestState>> {
+        let url = format!(
+            "{}/projects/{}/merge_requests?state=all&source_branch={}",
+            self.api_url.trim_end_matches('/'),
+            percent_encode(&repository_path(base_url)),
+            percent_encode(branch),
+        );
+        let header = self.token.as_ref().map(|x| format!("PRIVATE-TOKEN: {}", x));
+        let merge_requests = http_get_json_pages(&url, header.as_deref())?;
+        pick_state(
+            &merge_requests,
+            "/sha",
+            head_sha,
+            |merge_request| match merge_request["state"].as_str() {
+                Some("opened") | Some("locked") => Some(PullRequestState::Open),
+                Some("merged") => Some(PullRequestState::Merged),
+                Some("closed") => Some(PullRequestState::Closed),
+                _ => None,
+            },
+        )
+    }
+}
+
+pub struct Gitea {
+    pub api_url: String,
+    pub token: Option<String>,
+    /// Every pull request of each repository, since they are read for every branch.
+    pulls: Mutex<HashMap<String, Vec<Value>>>,
+}
+
+impl Gitea {
+    pub fn new(api_url: String, token: Option<String>) -> Gitea {
+        Gitea {
+            api_url,
+            token,
+            pulls: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Forge for Gitea {
+    fn pull_request_state(
+        &self,
+        base_url: &str,
+        head_url: &str,
+        branch: &str,
+        head_sha: &str,
+    ) -> Result<Option<PullRequestState>> {
+        // Gitea can't filter pull requests by the head branch, so every page is read.
+        let url = format!(
+            "{}/repos/{}/pulls?state=all&limit=50",
+            self.api_url.trim_end_matches('/'),
+            repository_path(base_url),
+        );
+        let header = self
+            .token
+            .as_ref()
+            .map(|x| format!("Authorization: token {}", x));
+        // It is locked while reading the pages, so that the other branches wait for them.
+        let mut cache = self.pulls.lock().expect("not poisoned");
+        if !cache.contains_key(&url) {
+            let pulls = http_get_json_pages(&url, header.as_deref())?;
+            cache.insert(url.clone(), pulls);
+        }
+        let head = repository_path(head_url);
+        let pulls: Vec<_> = cache[&url]
+            .iter()
+            .filter(|pull| pull["head"]["ref"].as_str() == Some(branch))
+            .filter(|pull| match pull["head"]["repo"]["full_name"].as_str() {
+                Some(full_name) => full_name == head,
+                None => true,
+            })
+            .cloned()
+            .collect();
+        pick_state(&pulls, "/head/sha", head_sha, |pull| {
+            match (pull["state"].as_str(), pull["merged"].as_bool()) {
+                (Some("open"), _) => Some(PullRequestState::Open),
+                (Some("closed"), Some(true)) => Some(PullRequestState::Merged),
+                (Some("closed"), _) => Some(PullRequestState::Closed),
+                _ => None,
+            }
+        })
+    }
+}
+
+/// `sha_pointer` is the JSON pointer to the head commit of a pull request.
+fn pick_state<F>(
+    pulls: &[Value],
+    sha_pointer: &str,
+    head_sha: &str,
+    state: F,
+) -> Result<Option<PullRequestState>>
+where
+    F: Fn(&Value) -> Option<PullRequestState>,
+{
+    let states: Vec<_> = pulls
+        .iter()
+        .filter(|pull| pull.pointer(sha_pointer).and_then(Value::as_str) == Some(head_sha))
+        .filter_map(state)
+        .collect();
+    for candidate in &[
+        PullRequestState::Open,
+        PullRequestState::Merged,
+        PullRequestState::Closed,
+    ] {
+        if states.contains(candidate) {
+            return Ok(Some(*candidate));
+        }
+    }
+    Ok(None)
+}
+
+/// `<owner>/<repo>` part of the remote url.
+/// https://host/owner/repo.git, ssh://git@host/owner/repo.git, git@host:owner/repo.git
+/// Local paths are just trimmed to the last two components.
+fn repository_path(url: &str) -> String {
+    let url = url.trim_end_matches('/');
+    let url = url.trim_end_matches(".git");
+    let path = if let Some(scheme) = url.find("://") {
+        let rest = &url[scheme + 3..];
+        rest.find('/').map(|slash| &rest[slash + 1..]).unwrap_or("")
+    } else if let Some(colon) = url.find(':') {
+        &url[colon + 1..]
+    } else {
+        let components: Vec<_> = url.split('/').filter(|x| !x.is_empty()).collect();
+        let skip = components.len().saturating_sub(2);
+        return components[skip..].join("/");
+    };
+    path.trim_start_matches('/').to_string()
+}
+
+fn percent_encode(value: &str) -> String {
+    let mut result = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                result.push(byte as char)
+            }
+            _ => result.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    result
+}
+
+/// Every page of a paginated list, following the `next` links in the `Link` header.
+fn http_get_json_pages(url: &str, header: Option<&str>) -> Result<Vec<Value>> {
+    let mut result = Vec::new();
+    let mut next = Some(url.to_string());
+    let mut visited = Vec::new();
+    while let Some(url) = next.take() {
+        let (headers, body) = http_get(&url, header)?;
+        match serde_json::from_slice(&body)? {
+            Value::Array(items) => result.extend(items),
+            _ => return Err(Error::Forge(format!("{} should be a list", url))),
+        }
+        visited.push(url);
+        next = next_link(&headers).filter(|next| !visited.contains(next));
+    }
+    Ok(result)
+}
+
+/// `<url>; rel="next"` in `Link: <url>; rel="prev", <url>; rel="next"`
+fn next_link(headers: &str) -> Option<String> {
+    for line in headers.lines() {
+        let mut split = line.splitn(2, ':');
+        match (split.next(), split.next()) {
+            (Some(name), Some(value)) if name.trim().eq_ignore_ascii_case("link") => {
+                for link in value.split(',') {
+                    let mut params = link.split(';');
+                    let target = params.next().unwrap_or_default().trim();
+                    if params.any(|param| param.trim() == "rel=\"next\"") {
+                        return Some(
+                            target
+                                .trim_start_matches('<')
+                                .trim_end_matches('>')
+                                .to_string(),
+                        );
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The headers of the last response after the redirects, and the body.
+/// The header is passed through the stdin not to expose the token in the process list.
+fn http_get(url: &str, header: Option<&str>) -> Result<(String, Vec<u8>)> {
+    let mut command = Command::new("curl");
+    command
+        .arg("--silent")
+        .arg("--show-error")
+        .arg("--fail")
+        .arg("--location")
+        .arg("--dump-header")
+        .arg("-")
+        .arg("--header")
+        .arg("Accept: application/json");
+    if header.is_some() {
+        command.arg("--header").arg("@-");
+    }
+    command
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    info!("> curl {}", url);
+    let mut child = command
+        .spawn()
+        .map_err(|err| Error::Forge(format!("Failed to run curl: {}", err)))?;
+    {
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        if let Some(header) = header {
+            writeln!(stdin, "{}", header)?;
+        }
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(Error::Forge(format!(
+            "Failed to request {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    // Each response of the redirects has its own headers before the body.
+    let mut rest = &output.stdout[..];
+    let mut headers = String::new();
+    while rest.starts_with(b"HTTP/") {
+        let end = rest
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .ok_or_else(|| Error::Forge(format!("Malformed response from {}", url)))?;
+        headers = String::from_utf8_lossy(&rest[..end]).to_string();
+        rest = &rest[end + 4..];
+    }
+    Ok((headers, rest.to_vec()))
+}
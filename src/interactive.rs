@@ -0,0 +1,82 @@
+use std::collections::HashSet;
+
+use crate::error::Result;
+use dialoguer::Checkboxes;
+use git2::Repository;
+
+use crate::report::{RefReport, Report, Status};
+use crate::{MergedOrGone, MergedOrGoneAndKeptBacks};
+
+/// Let the user pick the branches to delete from a checklist.
+/// Every candidate is selected by default, and they are grouped by their categories.
+pub fn select(repo: &Repository, branches: &MergedOrGoneAndKeptBacks) -> Result<MergedOrGone> {
+    let report = branches.report(repo)?;
+    let candidates = candidates(&report);
+    if candidates.is_empty() {
+        return Ok(branches.to_delete.clone());
+    }
+
+    let mut items = Vec::new();
+    for candidate in &candidates {
+        let subject = repo
+            .find_reference(&candidate.refname)?
+            .peel_to_commit()?
+            .summary()
+            .unwrap_or_default()
+            .to_string();
+        let local_only = if candidate.local_only {
+            " (local only)"
+        } else {
+            ""
+        };
+        let mut item = format!(
+            "[{}] {}{}: {}",
+            candidate.status, candidate.name, local_only, subject
+        );
+        if let Some(reason) = &candidate.reason {
+            item.push_str(&format!(" ({})", reason));
+        }
+        items.push(item);
+    }
+
+    let selected = Checkboxes::new()
+        .with_prompt("Select branches to delete")
+        .items(&items)
+        .defaults(&vec![true; items.len()])
+        .paged(true)
+        .interact()?;
+
+    Ok(retain_selected(&branches.to_delete, &candidates, &selected))
+}
+
+/// The items of the checklist, which are the branches to delete sorted by their categories.
+pub fn candidates(report: &Report) -> Vec<&RefReport> {
+    let mut candidates: Vec<&RefReport> = report
+        .local_branches
+        .iter()
+        .chain(report.remote_refs.iter())
+        .filter(|x| x.status != Status::KeptBack && x.status != Status::Remain)
+        .collect();
+    candidates.sort_by(|a, b| (a.status, &a.refname).cmp(&(b.status, &b.refname)));
+    candidates
+}
+
+/// The branches to delete that are selected by their indices in `candidates`.
+pub fn retain_selected(
+    to_delete: &MergedOrGone,
+    candidates: &[&RefReport],
+    selected: &[usize],
+) -> MergedOrGone {
+    // Local branches are deleted by their names, and remote refs are deleted by their refnames.
+    let selected: HashSet<_> = selected
+        .iter()
+        .map(|index| candidates[*index])
+        .map(|candidate| match candidate.status {
+            Status::MergedLocal | Status::GoneLocal | Status::StaleLocal => candidate.name.clone(),
+            _ => candidate.refname.clone(),
+        })
+        .collect();
+    let mut result = to_delete.clone();
+    result.retain(|x| selected.contains(x));
+    result
+}
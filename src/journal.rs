@@ -0,0 +1,306 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{Error, Result, ResultExt};
+use git2::{BranchType, Config, ErrorCode, Oid, Repository};
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{self, ConfigValue};
+use crate::deletion::DeletionReport;
+use crate::remote_ref::get_ref_on_remote_from_remote_ref;
+use crate::subprocess::{self, abbrev};
+
+/// A record of the branches that a run of git-trim deletes.
+/// It is taken before the deletion since the tips and the upstream configs are gone after it,
+/// and is saved under `$GIT_DIR/trim/journal/<run id>.json` with only the refs that are deleted,
+/// so that they can be restored with `git trim --undo`.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug)]
+pub struct Journal {
+    pub run_id: String,
+    pub local_branches: Vec<LocalBranchEntry>,
+    pub remote_refs: Vec<RemoteRefEntry>,
+    /// Remote refs that are deleted only from the local repository.
+    #[serde(default)]
+    pub local_remote_refs: Vec<LocalRemoteRefEntry>,
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug)]
+pub struct LocalBranchEntry {
+    pub name: String,
+    pub refname: String,
+    pub tip: String,
+    /// branch.<name>.remote
+    pub remote: Option<String>,
+    /// branch.<name>.merge
+    pub merge: Option<String>,
+    /// branch.<name>.pushRemote
+    pub push_remote: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug)]
+pub struct RemoteRefEntry {
+    /// refs/remotes/<remote>/<branch>
+    pub remote_ref: String,
+    pub remote_name: String,
+    /// The refname on the remote. e.g. refs/heads/<branch>
+    pub refname: String,
+    pub tip: String,
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug)]
+pub struct LocalRemoteRefEntry {
+    /// refs/remotes/<remote>/<branch>
+    pub remote_ref: String,
+    pub tip: String,
+}
+
+impl Journal {
+    pub fn record(
+        repo: &Repository,
+        config: &Config,
+        branches: &[&str],
+        remote_refs: &[&str],
+        local_remote_refs: &[&str],
+    ) -> Result<Journal> {
+        let run_id = SystemTime::now()
+            .duration_since(UNIX_EPOCH)?
+            .as_millis()
+            .to_string();
+
+        let mut local_branches = Vec::new();
+        for branch_name in branches {
+            let branch = repo.find_branch(branch_name, BranchType::Local)?;
+            let reference = branch.get();
+            let refname = reference
+                .name()
+                .ok_or_else(|| Error::non_utf8("branch ref"))?;
+            let tip = reference.peel_to_commit()?.id();
+            let read = |key: &str| -> Result<Option<String>> {
+                let key = format!("branch.{}.{}", branch_name, key);
+                Ok(config::get(config, &key)
+                    .read()?
+                    .map(ConfigValue::<String>::unwrap))
+            };
+            local_branches.push(LocalBranchEntry {
+                name: (*branch_name).to_string(),
+                refname: refname.to_string(),
+                tip: tip.to_string(),
+                remote: read("remote")?,
+                merge: read("merge")?,
+                push_remote: read("pushRemote")?,
+            });
+        }
+
+        let mut remote_ref_entries = Vec::new();
+        for remote_ref in remote_refs {
+            let reference = repo.find_reference(remote_ref)?;
+            let tip = reference.peel_to_commit()?.id();
+            let ref_on_remote = get_ref_on_remote_from_remote_ref(repo, remote_ref)?
+                .ok_or_else(|| Error::MissingRemote(remote_ref.to_string()))?;
+            remote_ref_entries.push(RemoteRefEntry {
+                remote_ref: (*remote_ref).to_string(),
+                remote_name: ref_on_remote.remote_name,
+                refname: ref_on_remote.refname,
+                tip: tip.to_string(),
+            });
+        }
+
+        let mut local_remote_ref_entries = Vec::new();
+        for remote_ref in local_remote_refs {
+            let tip = repo.find_reference(remote_ref)?.peel_to_commit()?.id();
+            local_remote_ref_entries.push(LocalRemoteRefEntry {
+                remote_ref: (*remote_ref).to_string(),
+                tip: tip.to_string(),
+            });
+        }
+
+        Ok(Journal {
+            run_id,
+            local_branches,
+            remote_refs: remote_ref_entries,
+            local_remote_refs: local_remote_ref_entries,
+        })
+    }
+
+    /// Leave out the refs that are not deleted, e.g. the ones that are changed on remote since fetch.
+    pub fn retain_deleted(&mut self, report: &DeletionReport) {
+        let deleted: HashSet<_> = report.deleted.iter().map(String::as_str).collect();
+        self.local_branches
+            .retain(|entry| deleted.contains(entry.name.as_str()));
+        self.remote_refs
+            .retain(|entry| deleted.contains(entry.remote_ref.as_str()));
+        self.local_remote_refs
+            .retain(|entry| deleted.contains(entry.remote_ref.as_str()));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.local_branches.is_empty()
+            && self.remote_refs.is_empty()
+            && self.local_remote_refs.is_empty()
+    }
+
+    pub fn save(&self, repo: &Repository) -> Result<PathBuf> {
+        let dir = journal_dir(repo);
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{}.json", self.run_id));
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+
+    /// Load the journal of the given run id, or the latest one.
+    pub fn load(repo: &Repository, run_id: Option<&str>) -> Result<Journal> {
+        let run_id = match run_id {
+            Some(run_id) => run_id.to_string(),
+            None => {
+                latest_run_id(repo)?.ok_or_else(|| Error::message("There is no journal to undo"))?
+            }
+        };
+        let path = journal_dir(repo).join(format!("{}.json", run_id));
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read journal {}", path.display()))?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn remove(&self, repo: &Repository) -> Result<()> {
+        let path = journal_dir(repo).join(format!("{}.json", self.run_id));
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    /// Recreate the local branches with their tracking configs and the remote tracking refs.
+    /// Deleted remote branches are pushed again if `push` is true.
+    /// The remote refs that are deleted only from the local repository are never pushed.
+    /// Each failure is reported and the others are restored anyway.
+    /// The restored ones are skipped when it is run again.
+    pub fn undo(&self, repo: &Repository, push: bool, dry_run: bool) -> Result<()> {
+        let mut failures = 0;
+        for entry in &self.local_branches {
+            if let Err(err) = restore_branch(repo, entry, dry_run) {
+                eprintln!("Failed to restore branch {}: {}", entry.name, err);
+                failures += 1;
+            }
+        }
+
+        for entry in &self.remote_refs {
+            let pushed = if push {
+                subprocess::push_restore(
+                    repo,
+                    &entry.remote_name,
+                    &entry.tip,
+                    &entry.refname,
+                    dry_run,
+                )
+            } else {
+                Ok(())
+            };
+            let result = pushed
+                .and_then(|()| restore_remote_ref(repo, &entry.remote_ref, &entry.tip, dry_run));
+            if let Err(err) = result {
+                eprintln!("Failed to restore remote ref {}: {}", entry.remote_ref, err);
+                failures += 1;
+            }
+        }
+
+        for entry in &self.local_remote_refs {
+            if let Err(err) = restore_remote_ref(repo, &entry.remote_ref, &entry.tip, dry_run) {
+                eprintln!("Failed to restore remote ref {}: {}", entry.remote_ref, err);
+                failures += 1;
+            }
+        }
+
+        if failures > 0 {
+            return Err(Error::message(format!(
+                "Failed to restore {} ref(s)",
+                failures
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn restore_branch(repo: &Repository, entry: &LocalBranchEntry, dry_run: bool) -> Result<()> {
+    let commit = repo.find_commit(Oid::from_str(&entry.tip)?)?;
+    match repo.find_branch(&entry.name, BranchType::Local) {
+        Ok(_) => {
+            warn!("Skip: the branch already exists: {}", entry.name);
+            return Ok(());
+        }
+        Err(err) if err.code() == ErrorCode::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+    if dry_run {
+        message!(
+            "Restore branch {} at {} (dry run).",
+            entry.name,
+            abbrev(&entry.tip)
+        );
+        return Ok(());
+    }
+    repo.branch(&entry.name, &commit, false)?;
+    let mut config = repo.config()?;
+    let settings = [
+        ("remote", &entry.remote),
+        ("merge", &entry.merge),
+        ("pushRemote", &entry.push_remote),
+    ];
+    for (key, value) in settings.iter() {
+        if let Some(value) = value {
+            config.set_str(&format!("branch.{}.{}", entry.name, key), value)?;
+        }
+    }
+    message!("Restored branch {} at {}.", entry.name, abbrev(&entry.tip));
+    Ok(())
+}
+
+fn restore_remote_ref(repo: &Repository, remote_ref: &str, tip: &str, dry_run: bool) -> Result<()> {
+    let oid = Oid::from_str(tip)?;
+    if repo.find_reference(remote_ref).is_ok() {
+        return Ok(());
+    }
+    if dry_run {
+        message!(
+            "Restore remote ref {} at {} (dry run).",
+            remote_ref,
+            abbrev(tip)
+        );
+        return Ok(());
+    }
+    repo.reference(remote_ref, oid, false, "git-trim: undo")?;
+    message!("Restored remote ref {} at {}.", remote_ref, abbrev(tip));
+    Ok(())
+}
+
+fn journal_dir(repo: &Repository) -> PathBuf {
+    repo.path().join("trim").join("journal")
+}
+
+fn latest_run_id(repo: &Repository) -> Result<Option<String>> {
+    let dir = journal_dir(repo);
+    if !dir.exists() {
+        return Ok(None);
+    }
+    let mut latest: Option<(u128, String)> = None;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|x| x.to_str()) != Some("json") {
+            continue;
+        }
+        let run_id = path
+            .file_stem()
+            .and_then(|x| x.to_str())
+            .ok_or_else(|| Error::non_utf8("journal name"))?;
+        if let Ok(timestamp) = run_id.parse::<u128>() {
+            let is_later = match &latest {
+                Some((latest, _)) => *latest < timestamp,
+                None => true,
+            };
+            if is_later {
+                latest = Some((timestamp, run_id.to_string()));
+            }
+        }
+    }
+    Ok(latest.map(|(_, run_id)| run_id))
+}
@@ -1,25 +1,73 @@
+/// Print a message for humans.
+/// It goes to stderr instead while stdout is reserved for a machine readable output.
+#[macro_export]
+macro_rules! message {
+    ($($arg:tt)*) => {
+        if $crate::is_stdout_reserved() {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
+pub mod archive;
 pub mod args;
+pub mod batch;
 pub mod config;
+pub mod deletion;
+pub mod error;
+pub mod explain;
+pub mod forge;
+pub mod interactive;
+pub mod journal;
+pub mod native;
 mod remote_ref;
+pub mod report;
 mod simple_glob;
-mod subprocess;
+pub mod subprocess;
 
 use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use anyhow::{Context, Result};
 use git2::{BranchType, Config as GitConfig, Error as GitError, ErrorCode, Repository};
 use glob::Pattern;
 use log::*;
 use rayon::prelude::*;
+use regex::Regex;
+use serde::Serialize;
 
 use crate::args::DeleteFilter;
+use crate::config::ConfigValue;
+use crate::deletion::DeletionReport;
+use crate::error::ResultExt;
+pub use crate::error::{Error, Result};
+use crate::forge::{Forge, PullRequestState};
 use crate::remote_ref::{
-    get_fetch_remote_ref, get_push_remote_ref, get_ref_on_remote_from_remote_ref,
+    get_fetch_remote_ref, get_ref_on_remote_from_remote_ref, get_remote_ref_from_ref_on_remote,
 };
+pub use crate::remote_ref::{get_push_ref_on_remote, get_push_remote_ref, RefOnRemote};
 pub use crate::subprocess::remote_update;
 
+/// The notes ref whose notes protect the branches at the noted commits.
+pub const PROTECT_NOTES_REF: &str = "refs/notes/trim-protect";
+
+static STDOUT_RESERVED: AtomicBool = AtomicBool::new(false);
+
+/// Reserve stdout for a machine readable output like `--format json`.
+/// After this, messages for humans and the outputs of git go to stderr.
+pub fn reserve_stdout() {
+    STDOUT_RESERVED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_stdout_reserved() -> bool {
+    STDOUT_RESERVED.load(Ordering::Relaxed)
+}
+
 pub struct Git {
     pub repo: Repository,
     pub config: GitConfig,
@@ -39,25 +87,41 @@ pub struct Config<'a> {
     pub protected_branches: HashSet<&'a str>,
     pub filter: DeleteFilter,
     pub detach: bool,
+    /// Days after which a branch is stale if it is neither merged nor updated.
+    pub stale_after: Option<u64>,
+    /// The pull request states from the forge are used in addition to the git history.
+    pub forge: Option<&'a dyn Forge>,
 }
 
-#[derive(Default, Eq, PartialEq, Debug)]
+#[derive(Default, Eq, PartialEq, Debug, Clone, Serialize)]
 pub struct MergedOrGone {
     // local branches
     pub merged_locals: HashSet<String>,
     pub gone_locals: HashSet<String>,
+    pub stale_locals: HashSet<String>,
 
     /// remote refs
     pub merged_remotes: HashSet<String>,
     pub gone_remotes: HashSet<String>,
+    pub stale_remotes: HashSet<String>,
+    /// remote refs that no remote can make with its fetch refspecs.
+    /// They are deleted only from the local repository.
+    pub orphaned_remotes: HashSet<String>,
+    /// merged, gone or stale remote refs that are deleted only from the local repository
+    /// without pushing.
+    pub local_only: HashSet<String>,
 }
 
 impl MergedOrGone {
     pub fn accumulate(mut self, mut other: Self) -> Self {
         self.merged_locals.extend(other.merged_locals.drain());
         self.gone_locals.extend(other.gone_locals.drain());
+        self.stale_locals.extend(other.stale_locals.drain());
         self.merged_remotes.extend(other.merged_remotes.drain());
         self.gone_remotes.extend(other.gone_remotes.drain());
+        self.stale_remotes.extend(other.stale_remotes.drain());
+        self.orphaned_remotes.extend(other.orphaned_remotes.drain());
+        self.local_only.extend(other.local_only.drain());
 
         self
     }
@@ -66,18 +130,96 @@ impl MergedOrGone {
         self.merged_locals
             .iter()
             .chain(self.gone_locals.iter())
+            .chain(self.stale_locals.iter())
             .map(String::as_str)
             .collect()
     }
 
+    /// Remote refs to delete from the remotes.
     pub fn remotes(&self) -> Vec<&str> {
         self.merged_remotes
             .iter()
             .chain(self.gone_remotes.iter())
+            .chain(self.stale_remotes.iter())
+            .filter(|remote_ref| !self.local_only.contains(*remote_ref))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Remote refs to delete only from the local repository.
+    pub fn local_only_remotes(&self) -> Vec<&str> {
+        self.merged_remotes
+            .iter()
+            .chain(self.gone_remotes.iter())
+            .chain(self.stale_remotes.iter())
+            .filter(|remote_ref| self.local_only.contains(*remote_ref))
             .map(String::as_str)
             .collect()
     }
 
+    /// Is there nothing to delete?
+    pub fn is_empty(&self) -> bool {
+        self.locals().is_empty()
+            && self.remotes().is_empty()
+            && self.local_only_remotes().is_empty()
+            && self.orphans().is_empty()
+    }
+
+    pub fn orphans(&self) -> Vec<&str> {
+        self.orphaned_remotes.iter().map(String::as_str).collect()
+    }
+
+    /// Keep only the local branch names and the remote refnames that satisfy the predicate.
+    pub fn retain<F>(&mut self, predicate: F)
+    where
+        F: Fn(&String) -> bool,
+    {
+        for (_, branches) in self.locals_mut() {
+            branches.retain(|x| predicate(x));
+        }
+        for (_, remote_refs) in self.remotes_mut() {
+            remote_refs.retain(|x| predicate(x));
+        }
+    }
+
+    /// Local branches for each category with its label for the messages.
+    fn locals_mut(&mut self) -> Vec<(&'static str, &mut HashSet<String>)> {
+        vec![
+            ("Merged local", &mut self.merged_locals),
+            ("Gone local", &mut self.gone_locals),
+            ("Stale local", &mut self.stale_locals),
+        ]
+    }
+
+    /// Remote refs for each category with its label for the messages.
+    fn remotes_mut(&mut self) -> Vec<(&'static str, &mut HashSet<String>)> {
+        vec![
+            ("Merged remotes", &mut self.merged_remotes),
+            ("Gone remotes", &mut self.gone_remotes),
+            ("Stale remotes", &mut self.stale_remotes),
+            ("Orphaned remotes", &mut self.orphaned_remotes),
+        ]
+    }
+
+    /// A branch can be merged into a base, but not into another base.
+    /// Being merged takes precedence over being gone or stale.
+    fn remove_stale_if_merged_or_gone(&mut self) {
+        let merged_or_gone_locals: HashSet<_> = self
+            .merged_locals
+            .union(&self.gone_locals)
+            .cloned()
+            .collect();
+        self.stale_locals
+            .retain(|x| !merged_or_gone_locals.contains(x));
+        let merged_or_gone_remotes: HashSet<_> = self
+            .merged_remotes
+            .union(&self.gone_remotes)
+            .cloned()
+            .collect();
+        self.stale_remotes
+            .retain(|x| !merged_or_gone_remotes.contains(x));
+    }
+
     fn apply_filter(&mut self, repo: &Repository, filter: &DeleteFilter) -> Result<()> {
         trace!("Before filter: {:#?}", self);
         trace!("Applying filter: {:?}", filter);
@@ -89,66 +231,62 @@ impl MergedOrGone {
             trace!("filter-out: gone local branches {:?}", self.merged_locals);
             self.gone_locals.clear();
         }
-
-        let mut merged_remotes = HashSet::new();
-        for remote_ref in &self.merged_remotes {
-            let ref_on_remote = get_ref_on_remote_from_remote_ref(repo, remote_ref)?;
-            if filter.filter_merged_remote(&ref_on_remote.remote_name) {
-                merged_remotes.insert(remote_ref.clone());
-            } else {
-                trace!("filter-out: merged remote ref {}", remote_ref);
-            }
+        if !filter.filter_stale_local() {
+            trace!("filter-out: stale local branches {:?}", self.stale_locals);
+            self.stale_locals.clear();
         }
-        self.merged_remotes = merged_remotes;
 
-        let mut gone_remotes = HashSet::new();
-        for remote_ref in &self.gone_remotes {
-            let ref_on_remote = get_ref_on_remote_from_remote_ref(repo, remote_ref)?;
-            if filter.filter_gone_remote(&ref_on_remote.remote_name) {
-                gone_remotes.insert(remote_ref.clone());
-            } else {
-                trace!("filter-out: gone_remotes remote ref {}", remote_ref);
-            }
+        self.merged_remotes = filter_remotes(
+            repo,
+            &self.merged_remotes,
+            &mut self.local_only,
+            "merged",
+            |remote| filter.filter_merged_remote(remote),
+            |remote| filter.filter_merged_remote_local(remote),
+        )?;
+        self.gone_remotes = filter_remotes(
+            repo,
+            &self.gone_remotes,
+            &mut self.local_only,
+            "gone",
+            |remote| filter.filter_gone_remote(remote),
+            |remote| filter.filter_gone_remote_local(remote),
+        )?;
+        self.stale_remotes = filter_remotes(
+            repo,
+            &self.stale_remotes,
+            &mut self.local_only,
+            "stale",
+            |remote| filter.filter_stale_remote(remote),
+            |remote| filter.filter_stale_remote_local(remote),
+        )?;
+
+        if !filter.filter_orphaned() {
+            trace!(
+                "filter-out: orphaned remote refs {:?}",
+                self.orphaned_remotes
+            );
+            self.orphaned_remotes.clear();
         }
-        self.gone_remotes = gone_remotes;
 
         Ok(())
     }
 }
 
-#[derive(Default, Eq, PartialEq, Debug)]
+#[derive(Default, Eq, PartialEq, Debug, Serialize)]
 pub struct MergedOrGoneAndKeptBacks {
     pub to_delete: MergedOrGone,
     pub kept_back: HashMap<String, String>,
+    /// Gone local branches with the number of commits that are on neither a remote ref nor a base.
+    pub unpushed_commits: HashMap<String, usize>,
+    pub classifications: Vec<Classification>,
 }
 
 impl MergedOrGoneAndKeptBacks {
     fn keep_base(&mut self, repo: &Repository, config: &GitConfig, bases: &[&str]) -> Result<()> {
         let base_refs = resolve_base_refs(repo, config, bases)?;
         trace!("base_refs: {:#?}", base_refs);
-        self.kept_back.extend(keep_branches(
-            repo,
-            &base_refs,
-            "Merged local but kept back because it is a base",
-            &mut self.to_delete.merged_locals,
-        )?);
-        self.kept_back.extend(keep_branches(
-            repo,
-            &base_refs,
-            "Gone local but kept back because it is a base",
-            &mut self.to_delete.gone_locals,
-        )?);
-        self.kept_back.extend(keep_remote_refs(
-            &base_refs,
-            "Merged remotes but kept back because it is a base",
-            &mut self.to_delete.merged_remotes,
-        ));
-        self.kept_back.extend(keep_remote_refs(
-            &base_refs,
-            "Gone remotes but kept back because it is a base",
-            &mut self.to_delete.gone_remotes,
-        ));
-        Ok(())
+        self.keep_refs(repo, &base_refs, "because it is a base")
     }
 
     fn keep_protected(
@@ -159,54 +297,208 @@ impl MergedOrGoneAndKeptBacks {
     ) -> Result<()> {
         let protected_refs = resolve_protected_refs(repo, config, protected_branches)?;
         trace!("protected_refs: {:#?}", protected_refs);
-        self.kept_back.extend(keep_branches(
-            repo,
-            &protected_refs,
-            "Merged local but kept back because it is protected",
-            &mut self.to_delete.merged_locals,
-        )?);
-        self.kept_back.extend(keep_branches(
-            repo,
-            &protected_refs,
-            "Gone local but kept back because it is protected",
-            &mut self.to_delete.gone_locals,
-        )?);
-        self.kept_back.extend(keep_remote_refs(
-            &protected_refs,
-            "Merged remotes but kept back because it is protected",
-            &mut self.to_delete.merged_remotes,
-        ));
-        self.kept_back.extend(keep_remote_refs(
-            &protected_refs,
-            "Gone remotes but kept back because it is protected",
-            &mut self.to_delete.gone_remotes,
-        ));
+        self.keep_refs(repo, &protected_refs, "because it is protected")
+    }
+
+    fn keep_refs(&mut self, repo: &Repository, refs: &HashSet<String>, why: &str) -> Result<()> {
+        for (label, branches) in self.to_delete.locals_mut() {
+            let reason = format!("{} but kept back {}", label, why);
+            self.kept_back
+                .extend(keep_branches(repo, refs, &reason, branches)?);
+        }
+        for (label, remote_refs) in self.to_delete.remotes_mut() {
+            let reason = format!("{} but kept back {}", label, why);
+            self.kept_back
+                .extend(keep_remote_refs(refs, &reason, remote_refs));
+        }
         Ok(())
     }
 
-    fn adjust_not_to_detach(&mut self, repo: &Repository) -> Result<()> {
+    /// A branch is protected individually by `git config branch.<name>.trimProtect true`
+    /// or by a note on its tip commit in `refs/notes/trim-protect`.
+    /// Its upstream and push destination are kept back together.
+    fn keep_marked_protected(&mut self, repo: &Repository, config: &GitConfig) -> Result<()> {
+        let mut marked = Vec::new();
+        for branch in repo.branches(Some(BranchType::Local))? {
+            let (branch, _) = branch?;
+            let branch_name = branch
+                .name()?
+                .ok_or_else(|| Error::non_utf8("branch name"))?;
+            if let Some(why) = get_marked_protection(repo, config, branch_name)? {
+                marked.push((branch_name.to_string(), why));
+            }
+        }
+
+        for (branch_name, why) in marked {
+            let remote_refs: HashSet<_> = self
+                .classifications
+                .iter()
+                .filter(|c| c.branch_name == branch_name)
+                .flat_map(|c| c.fetch.iter().chain(c.push.iter()).cloned())
+                .collect();
+            self.keep_local(&branch_name, &why);
+            for remote_ref in remote_refs {
+                self.keep_remote(&remote_ref, &why);
+            }
+        }
+        Ok(())
+    }
+
+    /// A branch with an open pull request is still under review even if it looks merged or gone.
+    fn keep_open_pull_requests(&mut self) {
+        let open: Vec<_> = self
+            .classifications
+            .iter()
+            .filter(|c| c.pull_request == Some(PullRequestState::Open))
+            .map(|c| (c.branch_name.clone(), c.fetch.clone(), c.push.clone()))
+            .collect();
+        for (branch_name, fetch, push) in open {
+            let why = "because its pull request is open";
+            self.keep_local(&branch_name, why);
+            for remote_ref in fetch.iter().chain(push.iter()) {
+                self.keep_remote(remote_ref, why);
+            }
+        }
+    }
+
+    /// The local branch is merged, but its remote ref has commits that are not merged.
+    /// The remote ref is not deleted unless another base justifies it on its own.
+    fn keep_diverged_remotes(&mut self) {
+        let to_delete: HashSet<_> = self
+            .to_delete
+            .remotes()
+            .into_iter()
+            .chain(self.to_delete.local_only_remotes())
+            .map(str::to_string)
+            .collect();
+        for c in &self.classifications {
+            if let Some(remote_ref) = &c.diverged_remote {
+                if !to_delete.contains(remote_ref) {
+                    self.kept_back.insert(
+                        remote_ref.to_string(),
+                        "local merged, remote diverged: it has commits that are not merged"
+                            .to_string(),
+                    );
+                }
+            }
+        }
+    }
+
+    /// A gone local branch might have commits that are never pushed anywhere,
+    /// and `git branch -D` would lose them. It is kept back unless `gone-unpushed-local` is given.
+    fn keep_gone_with_unpushed_work(
+        &mut self,
+        repo: &Repository,
+        config: &GitConfig,
+        bases: &[&str],
+        filter: &DeleteFilter,
+    ) -> Result<()> {
+        let base_refs = resolve_base_refs(repo, config, bases)?;
+        let mut gone_locals: Vec<_> = self.to_delete.gone_locals.iter().cloned().collect();
+        gone_locals.sort();
+        for branch_name in gone_locals {
+            if self.to_delete.merged_locals.contains(&branch_name) {
+                continue;
+            }
+            let count = count_unpushed_commits(repo, &base_refs, &branch_name)?;
+            if count == 0 {
+                continue;
+            }
+            self.unpushed_commits.insert(branch_name.clone(), count);
+            if !filter.filter_gone_unpushed_local() {
+                self.keep_local(
+                    &branch_name,
+                    &format!("because it has {} unpushed commit(s)", count),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Keep back a remote ref from every category.
+    fn keep_remote(&mut self, remote_ref: &str, why: &str) {
+        for (label, remote_refs) in self.to_delete.remotes_mut() {
+            if remote_refs.remove(remote_ref) {
+                self.kept_back.insert(
+                    remote_ref.to_string(),
+                    format!("{} but kept back {}", label, why),
+                );
+            }
+        }
+    }
+
+    /// Keep back a local branch from every category.
+    fn keep_local(&mut self, branch_name: &str, why: &str) {
+        for (label, branches) in self.to_delete.locals_mut() {
+            if branches.remove(branch_name) {
+                self.kept_back.insert(
+                    branch_name.to_string(),
+                    format!("{} but kept back {}", label, why),
+                );
+            }
+        }
+    }
+
+    /// HEAD of a bare repository points to the default branch of it.
+    /// It can't be detached since there is no working tree to checkout.
+    fn keep_bare_head(&mut self, repo: &Repository) -> Result<()> {
         if repo.head_detached()? {
             return Ok(());
         }
-        let head = repo.head()?;
-        let head_name = head.name().context("non-utf8 head ref name")?;
-        assert!(head_name.starts_with("refs/heads/"));
+        let head = match repo.head() {
+            Ok(head) => head,
+            Err(err) if err.code() == ErrorCode::UnbornBranch => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        let head_name = head
+            .name()
+            .ok_or_else(|| Error::non_utf8("head ref name"))?;
+        if !head_name.starts_with("refs/heads/") {
+            return Ok(());
+        }
         let head_name = &head_name["refs/heads/".len()..];
 
-        if self.to_delete.merged_locals.contains(head_name) {
-            self.to_delete.merged_locals.remove(head_name);
-            self.kept_back.insert(
-                head_name.to_string(),
-                "Merged local but kept back not to make detached HEAD".to_string(),
+        self.keep_local(head_name, "because it is HEAD of the bare repository");
+        Ok(())
+    }
+
+    /// A branch that is checked out in another linked worktree can't be deleted
+    /// unless that worktree is detached.
+    fn keep_worktree_heads(&mut self, repo: &Repository) -> Result<()> {
+        let current_worktree = repo.workdir().map(canonicalize);
+        // `git worktree list` shows the git dir of a submodule as its main worktree.
+        let git_dir = canonicalize(repo.path());
+        for (head_refname, worktree) in subprocess::get_worktree_heads(repo)? {
+            let worktree_path = canonicalize(Path::new(&worktree));
+            if Some(&worktree_path) == current_worktree.as_ref() || worktree_path == git_dir {
+                continue;
+            }
+            if !head_refname.starts_with("refs/heads/") {
+                continue;
+            }
+            let head_name = &head_refname["refs/heads/".len()..];
+            self.keep_local(
+                head_name,
+                &format!("because it is checked out in the worktree {}", worktree),
             );
         }
-        if self.to_delete.gone_locals.contains(head_name) {
-            self.to_delete.gone_locals.remove(head_name);
-            self.kept_back.insert(
-                head_name.to_string(),
-                "Gone local but kept back not to make detached HEAD".to_string(),
-            );
+        Ok(())
+    }
+
+    fn adjust_not_to_detach(&mut self, repo: &Repository) -> Result<()> {
+        if repo.head_detached()? {
+            return Ok(());
+        }
+        let head = repo.head()?;
+        let head_name = head
+            .name()
+            .ok_or_else(|| Error::non_utf8("head ref name"))?;
+        if !head_name.starts_with("refs/heads/") {
+            return Ok(());
         }
+        let head_name = &head_name["refs/heads/".len()..];
+
+        self.keep_local(head_name, "not to make detached HEAD");
         Ok(())
     }
 
@@ -216,17 +508,28 @@ impl MergedOrGoneAndKeptBacks {
         let local_branches_to_delete: HashSet<_> = self.to_delete.locals().into_iter().collect();
         for local_branch in repo.branches(Some(BranchType::Local))? {
             let (branch, _) = local_branch?;
-            let name = branch.name()?.context("non utf-8 local branch name")?;
+            let name = branch
+                .name()?
+                .ok_or_else(|| Error::non_utf8("local branch name"))?;
             if local_branches_to_delete.contains(name) {
                 continue;
             }
             println!("    {}", name);
         }
         println!("  remote references:");
-        let remote_refs_to_delete: HashSet<_> = self.to_delete.remotes().into_iter().collect();
+        let remote_refs_to_delete: HashSet<_> = self
+            .to_delete
+            .remotes()
+            .into_iter()
+            .chain(self.to_delete.local_only_remotes())
+            .chain(self.to_delete.orphans())
+            .collect();
         for remote_ref in repo.branches(Some(BranchType::Remote))? {
             let (branch, _) = remote_ref?;
-            let name = branch.get().name().context("non utf-8 remote ref name")?;
+            let name = branch
+                .get()
+                .name()
+                .ok_or_else(|| Error::non_utf8("remote ref name"))?;
             if remote_refs_to_delete.contains(name) {
                 continue;
             }
@@ -256,15 +559,63 @@ impl MergedOrGoneAndKeptBacks {
             }
         }
 
+        let local_only = &self.to_delete.local_only;
+        let print_remotes = |label: &str, remote_refs: &HashSet<String>| {
+            let (local, pushed): (HashSet<_>, HashSet<_>) = remote_refs
+                .iter()
+                .cloned()
+                .partition(|remote_ref| local_only.contains(remote_ref));
+            print(label, &pushed);
+            print(&format!("{} only from the local repository", label), &local);
+        };
+
         print("merged local branches", &self.to_delete.merged_locals);
-        print("merged remote refs", &self.to_delete.merged_remotes);
-        print("gone local branches", &self.to_delete.gone_locals);
-        print("gone remote refs", &self.to_delete.gone_remotes);
+        print_remotes("merged remote refs", &self.to_delete.merged_remotes);
+        let gone_locals: HashSet<_> = self
+            .to_delete
+            .gone_locals
+            .iter()
+            .map(|branch| match self.unpushed_commits.get(branch) {
+                Some(count) => format!("{} ({} unpushed commit(s))", branch, count),
+                None => branch.to_string(),
+            })
+            .collect();
+        print("gone local branches", &gone_locals);
+        print_remotes("gone remote refs", &self.to_delete.gone_remotes);
+        print("stale local branches", &self.to_delete.stale_locals);
+        print_remotes("stale remote refs", &self.to_delete.stale_remotes);
+        print("orphaned remote refs", &self.to_delete.orphaned_remotes);
 
         Ok(())
     }
 }
 
+/// Why the branch is protected individually if it is.
+fn get_marked_protection(
+    repo: &Repository,
+    config: &GitConfig,
+    branch_name: &str,
+) -> Result<Option<String>> {
+    let key = format!("branch.{}.trimProtect", branch_name);
+    if let Some(true) = config::get(config, &key).read()?.map(ConfigValue::unwrap) {
+        return Ok(Some(format!("because of 'git config {}'", key)));
+    }
+    let branch = repo.find_branch(branch_name, BranchType::Local)?;
+    if let Some(target) = branch.get().target() {
+        match repo.find_note(Some(PROTECT_NOTES_REF), target) {
+            Ok(_) => {
+                return Ok(Some(format!(
+                    "because of the note in {}",
+                    PROTECT_NOTES_REF
+                )))
+            }
+            Err(err) if err.code() == ErrorCode::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(None)
+}
+
 fn keep_branches(
     repo: &Repository,
     protected_refs: &HashSet<String>,
@@ -276,7 +627,9 @@ fn keep_branches(
     for branch_name in branches.iter() {
         let branch = repo.find_branch(branch_name, BranchType::Local)?;
         let reference = branch.into_reference();
-        let refname = reference.name().context("non utf-8 branch ref")?;
+        let refname = reference
+            .name()
+            .ok_or_else(|| Error::non_utf8("branch ref"))?;
         if protected_refs.contains(branch_name) {
             bag.insert(branch_name.to_string());
             bag.insert(refname.to_string());
@@ -329,10 +682,20 @@ pub fn get_merged_or_gone(git: &Git, config: &Config) -> Result<MergedOrGoneAndK
     let mut merged_locals = HashSet::new();
     merged_locals.extend(noff_merged_locals);
 
+    let stale_before = match config.stale_after {
+        Some(days) => {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+            Some(now - days as i64 * 24 * 60 * 60)
+        }
+        None => None,
+    };
+
     let mut base_and_branch_to_compare = Vec::new();
     for branch in git.repo.branches(Some(BranchType::Local))? {
         let (branch, _) = branch?;
-        let branch_name = branch.name()?.context("non-utf8 branch name")?;
+        let branch_name = branch
+            .name()?
+            .ok_or_else(|| Error::non_utf8("branch name"))?;
         debug!("Branch: {:?}", branch.name()?);
         if config::get_remote(&git.config, branch_name)?.is_implicit() {
             debug!(
@@ -367,85 +730,178 @@ pub fn get_merged_or_gone(git: &Git, config: &Config) -> Result<MergedOrGoneAndK
         }
     }
 
+    let pull_requests = match config.forge {
+        Some(forge) => get_pull_request_states(git, forge, &base_and_branch_to_compare)?,
+        None => HashMap::new(),
+    };
+
+    let repo_path = git.repo.path().to_path_buf();
     let classifications = base_and_branch_to_compare
         .into_par_iter()
-        .map({
-            // git's fields are semantically Send + Sync in the `classify`.
-            // They are read only in `classify` function.
-            // It is denoted that it is safe in that case
-            // https://github.com/libgit2/libgit2/blob/master/docs/threading.md#sharing-objects
-            let git = ForceSendSync(git);
-            move |(base_remote_ref, branch_name)| {
-                classify(git, &merged_locals, &base_remote_ref, &branch_name).with_context(|| {
-                    format!(
-                        "base_remote_ref={}, branch_name={}",
-                        base_remote_ref, branch_name
+        .map_init(
+            // `Repository` isn't `Sync`, so each worker opens its own to walk and diff the history.
+            move || Repository::open(&repo_path),
+            {
+                // git's fields are semantically Send + Sync in the `classify`.
+                // Only the config and the refs are read from them in `classify` function.
+                // It is denoted that it is safe in that case
+                // https://github.com/libgit2/libgit2/blob/master/docs/threading.md#sharing-objects
+                let git = ForceSendSync(git);
+                let pull_requests = &pull_requests;
+                move |repo, (base_remote_ref, branch_name)| {
+                    let repo = match repo {
+                        Ok(repo) => repo,
+                        Err(err) => return Err(Error::message(err)),
+                    };
+                    let pull_request = pull_requests
+                        .get(&(base_remote_ref.clone(), branch_name.clone()))
+                        .cloned();
+                    classify(
+                        git,
+                        repo,
+                        &merged_locals,
+                        stale_before,
+                        pull_request,
+                        &base_remote_ref,
+                        &branch_name,
                     )
-                })
-            }
-        })
+                    .with_context(|| {
+                        format!(
+                            "base_remote_ref={}, branch_name={}",
+                            base_remote_ref, branch_name
+                        )
+                    })
+                }
+            },
+        )
         .collect::<Result<Vec<_>, _>>()?;
 
-    for classification in classifications.into_iter() {
+    for classification in classifications.iter() {
         debug!("branch: {}", classification.branch_name);
+        trace!("base: {}", classification.base);
         trace!("merged: {}", classification.branch_is_merged);
+        trace!("stale: {}", classification.branch_is_stale);
+        trace!("pull request: {:?}", classification.pull_request);
         trace!("push: {:?}", classification.fetch);
         trace!("fetch: {:?}", classification.push);
         debug!("message: {}", classification.message);
-        merged_or_gone = merged_or_gone.accumulate(classification.result);
+        merged_or_gone = merged_or_gone.accumulate(classification.result.clone());
     }
+    merged_or_gone.remove_stale_if_merged_or_gone();
+    merged_or_gone.orphaned_remotes = get_orphaned_remote_refs(&git.repo)?;
     merged_or_gone.apply_filter(&git.repo, &config.filter)?;
 
     let mut result = MergedOrGoneAndKeptBacks {
         to_delete: merged_or_gone,
         kept_back: HashMap::new(),
+        unpushed_commits: HashMap::new(),
+        classifications,
     };
     result.keep_base(&git.repo, &git.config, &config.bases)?;
     result.keep_protected(&git.repo, &git.config, &config.protected_branches)?;
+    result.keep_marked_protected(&git.repo, &git.config)?;
+    result.keep_open_pull_requests();
+    result.keep_diverged_remotes();
+    result.keep_gone_with_unpushed_work(&git.repo, &git.config, &config.bases, &config.filter)?;
 
-    if !config.detach {
+    result.keep_worktree_heads(&git.repo)?;
+    if git.repo.is_bare() {
+        result.keep_bare_head(&git.repo)?;
+    } else if !config.detach {
         result.adjust_not_to_detach(&git.repo)?;
     }
 
     Ok(result)
 }
 
-struct Classification {
-    branch_name: String,
-    branch_is_merged: bool,
-    fetch: Option<String>,
-    push: Option<String>,
-    message: &'static str,
-    result: MergedOrGone,
+/// How a local branch is classified against a base.
+/// There is one classification for each pair of a base and a local branch.
+#[derive(Default, Eq, PartialEq, Debug, Serialize)]
+pub struct Classification {
+    pub branch_name: String,
+    pub base: String,
+    pub branch_is_merged: bool,
+    pub branch_is_stale: bool,
+    pub pull_request: Option<PullRequestState>,
+    pub fetch: Option<String>,
+    pub push: Option<String>,
+    /// The remote ref that is kept back because it is not merged while the local branch is.
+    pub diverged_remote: Option<String>,
+    pub message: &'static str,
+    pub result: MergedOrGone,
 }
 
 /// Make sure repo and config are semantically Send + Sync.
+/// The history is walked in `repo` which is owned by the worker.
 fn classify(
     git: ForceSendSync<&Git>,
+    repo: &Repository,
     merged_locals: &HashSet<String>,
+    stale_before: Option<i64>,
+    pull_request: Option<PullRequestState>,
     base_remote_ref: &str,
     branch_name: &str,
 ) -> Result<Classification> {
+    let closed = pull_request == Some(PullRequestState::Closed);
     let merged = merged_locals.contains(branch_name)
-        || subprocess::is_merged(&git.repo, base_remote_ref, branch_name)?;
+        || pull_request == Some(PullRequestState::Merged)
+        || is_merged(repo, base_remote_ref, branch_name)?;
     let fetch = get_fetch_remote_ref(&git.repo, &git.config, branch_name)?;
     let push = get_push_remote_ref(&git.repo, &git.config, branch_name)?;
+    // The remote ref is deleted as merged only if its own tip is merged too,
+    // since it might have commits that the local branch has never pulled.
+    let diverged_remote = match push.as_ref().or(fetch.as_ref()) {
+        Some(remote_ref)
+            if merged && !is_remote_merged(repo, base_remote_ref, branch_name, remote_ref)? =>
+        {
+            Some(remote_ref.clone())
+        }
+        _ => None,
+    };
+    let stale = match stale_before {
+        Some(stale_before) if !merged => {
+            let mut refs = vec![format!("refs/heads/{}", branch_name)];
+            refs.extend(fetch.iter().cloned());
+            refs.extend(push.iter().cloned());
+            is_stale(repo, &refs, stale_before)?
+        }
+        _ => false,
+    };
 
     let mut c = Classification {
         branch_name: branch_name.to_string(),
+        base: base_remote_ref.to_string(),
         branch_is_merged: merged,
+        branch_is_stale: stale,
+        pull_request,
         fetch: fetch.clone(),
         push: push.clone(),
+        diverged_remote: diverged_remote.clone(),
         message: "",
         result: MergedOrGone::default(),
     };
 
     match (fetch, push) {
+        (Some(_), Some(_)) | (Some(_), None) if diverged_remote.is_some() => {
+            c.message = "merged local, diverged remote: the branch is merged, but the remote has commits that are not merged";
+            c.result.merged_locals.insert(branch_name.to_string());
+        }
         (Some(_), Some(remote_ref)) if merged => {
             c.message = "merged local, merged remote: the branch is merged, but forgot to delete";
             c.result.merged_locals.insert(branch_name.to_string());
             c.result.merged_remotes.insert(remote_ref);
         }
+        (Some(_), Some(remote_ref)) if closed => {
+            c.message = "gone local, gone remote: the pull request is closed without merge";
+            c.result.gone_locals.insert(branch_name.to_string());
+            c.result.gone_remotes.insert(remote_ref);
+        }
+        (Some(_), Some(remote_ref)) if stale => {
+            c.message =
+                "stale local, stale remote: the branch is neither merged nor updated recently";
+            c.result.stale_locals.insert(branch_name.to_string());
+            c.result.stale_remotes.insert(remote_ref);
+        }
         (Some(_), Some(_)) => {
             c.message = "skip: live branch. not merged, not gone";
         }
@@ -457,10 +913,24 @@ fn classify(
             c.result.merged_locals.insert(branch_name.to_string());
             c.result.merged_remotes.insert(fetch_ref);
         }
+        (Some(fetch_ref), None) if closed => {
+            c.message = "gone local, gone remote: the pull request is closed without merge";
+            c.result.gone_locals.insert(branch_name.to_string());
+            c.result.gone_remotes.insert(fetch_ref);
+        }
+        (Some(fetch_ref), None) if stale => {
+            c.message =
+                "stale local, stale remote: the branch is neither merged nor updated recently";
+            c.result.stale_locals.insert(branch_name.to_string());
+            c.result.stale_remotes.insert(fetch_ref);
+        }
         (Some(_), None) => {
             c.message = "skip: it might be a long running branch like 'develop' in a git-flow";
         }
 
+        (None, Some(_)) if diverged_remote.is_some() => {
+            c.message = "diverged remote: it might be a long running branch like 'develop' which is once pushed to the personal git.repo in the triangular workflow, and the local branch is merged on the upstream, but the remote has commits that are not merged";
+        }
         (None, Some(remote_ref)) if merged => {
             c.message = "merged remote: it might be a long running branch like 'develop' which is once pushed to the personal git.repo in the triangular workflow, but the branch is merged on the upstream";
             c.result.merged_remotes.insert(remote_ref);
@@ -483,6 +953,173 @@ fn classify(
     Ok(c)
 }
 
+/// What the forge is asked about the pull request of a branch.
+/// The bases on the same remote share it, so it is asked only once for them.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+struct PullRequestQuery {
+    base_url: String,
+    head_url: String,
+    branch: String,
+    head_sha: String,
+}
+
+/// The pull request from the push destination of the branch to the remote of the base.
+fn get_pull_request_query(
+    git: &Git,
+    base_remote_ref: &str,
+    branch_name: &str,
+) -> Result<Option<PullRequestQuery>> {
+    let head = match get_push_ref_on_remote(&git.repo, &git.config, branch_name)? {
+        Some(head) => head,
+        None => {
+            debug!("Skip the forge: {} has no push destination", branch_name);
+            return Ok(None);
+        }
+    };
+    let base = match get_ref_on_remote_from_remote_ref(&git.repo, base_remote_ref)? {
+        Some(base) => base,
+        None => {
+            debug!("Skip the forge: {} is not on a remote", base_remote_ref);
+            return Ok(None);
+        }
+    };
+    let base_url = config::get_remote_url(&git.config, &base.remote_name)?;
+    let head_url = config::get_remote_url(&git.config, &head.remote_name)?;
+    let (base_url, head_url) = match (base_url, head_url) {
+        (Some(base_url), Some(head_url)) => (base_url, head_url),
+        _ => {
+            debug!("Skip the forge: the remotes of {} have no url", branch_name);
+            return Ok(None);
+        }
+    };
+    // The pull requests are matched by the commit that is pushed,
+    // not to take an old pull request of a reused branch name.
+    let head_sha = match get_remote_ref_from_ref_on_remote(&git.repo, &git.config, &head)? {
+        Some(remote_ref) => git.repo.refname_to_id(&remote_ref)?.to_string(),
+        None => {
+            debug!(
+                "Skip the forge: {} on {} is not fetched",
+                head.refname, head.remote_name
+            );
+            return Ok(None);
+        }
+    };
+    Ok(Some(PullRequestQuery {
+        base_url,
+        head_url,
+        branch: head.refname.trim_start_matches("refs/heads/").to_string(),
+        head_sha,
+    }))
+}
+
+/// Ask the forge about the pull requests of the pairs of a base and a branch.
+/// Each pull request is asked only once. It only warns on errors since the forge is
+/// an additional signal.
+fn get_pull_request_states(
+    git: &Git,
+    forge: &dyn Forge,
+    base_and_branches: &[(String, String)],
+) -> Result<HashMap<(String, String), PullRequestState>> {
+    let mut queries = HashMap::new();
+    for (base_remote_ref, branch_name) in base_and_branches {
+        if let Some(query) = get_pull_request_query(git, base_remote_ref, branch_name)? {
+            queries.insert((base_remote_ref.clone(), branch_name.clone()), query);
+        }
+    }
+    let unique: HashSet<&PullRequestQuery> = queries.values().collect();
+    let states: HashMap<&PullRequestQuery, PullRequestState> = unique
+        .into_par_iter()
+        .filter_map(|query| {
+            match forge.pull_request_state(
+                &query.base_url,
+                &query.head_url,
+                &query.branch,
+                &query.head_sha,
+            ) {
+                Ok(state) => state.map(|state| (query, state)),
+                Err(err) => {
+                    warn!(
+                        "Failed to get the pull request of {} from the forge: {}",
+                        query.branch, err
+                    );
+                    None
+                }
+            }
+        })
+        .collect();
+    Ok(queries
+        .iter()
+        .filter_map(|(key, query)| states.get(query).map(|state| (key.clone(), *state)))
+        .collect())
+}
+
+/// Is the remote ref of the merged local branch merged on its own?
+/// It is if it is behind the local branch, or its own commits are merged into the base.
+fn is_remote_merged(
+    repo: &Repository,
+    base_remote_ref: &str,
+    branch_name: &str,
+    remote_ref: &str,
+) -> Result<bool> {
+    let local = repo.refname_to_id(&format!("refs/heads/{}", branch_name))?;
+    let remote = repo.refname_to_id(remote_ref)?;
+    if local == remote || repo.graph_descendant_of(local, remote)? {
+        return Ok(true);
+    }
+    is_merged(repo, base_remote_ref, remote_ref)
+}
+
+/// The number of commits of the branch that are reachable from neither remote refs nor bases.
+fn count_unpushed_commits(
+    repo: &Repository,
+    base_refs: &HashSet<String>,
+    branch_name: &str,
+) -> Result<usize> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push_ref(&format!("refs/heads/{}", branch_name))?;
+    revwalk.hide_glob("refs/remotes/*")?;
+    for base_ref in base_refs {
+        if base_ref.starts_with("refs/") {
+            revwalk.hide_ref(base_ref)?;
+        }
+    }
+    let mut count = 0;
+    for oid in revwalk {
+        oid?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Are tips of all refs committed before the given time?
+fn is_stale(repo: &Repository, refs: &[String], stale_before: i64) -> Result<bool> {
+    for refname in refs {
+        let commit = repo.find_reference(refname)?.peel_to_commit()?;
+        if commit.time().seconds() >= stale_before {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn canonicalize(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Test with libgit2 first, and fallback to the git subprocess if it fails.
+pub fn is_merged(repo: &Repository, base_remote_ref: &str, branch: &str) -> Result<bool> {
+    match native::is_merged(repo, base_remote_ref, branch) {
+        Ok(merged) => Ok(merged),
+        Err(err) => {
+            warn!(
+                "Fallback to the git subprocess to test whether {} is merged into {}: {}",
+                branch, base_remote_ref, err
+            );
+            subprocess::is_merged(repo, base_remote_ref, branch)
+        }
+    }
+}
+
 /// Use with caution.
 /// It makes wrapping type T to be Send + Sync.
 /// Make sure T is semantically Send + Sync
@@ -500,6 +1137,55 @@ impl<T> Deref for ForceSendSync<T> {
     }
 }
 
+/// Filter out remote refs of the remotes that are not selected.
+/// The ones that are selected to be deleted only from the local repository are marked as `local_only`.
+fn filter_remotes<F, G>(
+    repo: &Repository,
+    remote_refs: &HashSet<String>,
+    local_only: &mut HashSet<String>,
+    label: &str,
+    filter_remote: F,
+    filter_remote_local: G,
+) -> Result<HashSet<String>>
+where
+    F: Fn(&str) -> bool,
+    G: Fn(&str) -> bool,
+{
+    let mut result = HashSet::new();
+    for remote_ref in remote_refs {
+        match get_ref_on_remote_from_remote_ref(repo, remote_ref)? {
+            Some(ref_on_remote) if filter_remote_local(&ref_on_remote.remote_name) => {
+                result.insert(remote_ref.clone());
+                local_only.insert(remote_ref.clone());
+            }
+            Some(ref_on_remote) if filter_remote(&ref_on_remote.remote_name) => {
+                result.insert(remote_ref.clone());
+            }
+            _ => {
+                trace!("filter-out: {} remote ref {}", label, remote_ref);
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Remote refs that are left behind after the remote is removed or renamed,
+/// or its fetch refspec is changed.
+fn get_orphaned_remote_refs(repo: &Repository) -> Result<HashSet<String>> {
+    let mut result = HashSet::new();
+    for remote_ref in repo.branches(Some(BranchType::Remote))? {
+        let (branch, _) = remote_ref?;
+        let refname = branch
+            .get()
+            .name()
+            .ok_or_else(|| Error::non_utf8("remote ref name"))?;
+        if get_ref_on_remote_from_remote_ref(repo, refname)?.is_none() {
+            result.insert(refname.to_string());
+        }
+    }
+    Ok(result)
+}
+
 /// if there are following references:
 /// refs/heads/master
 /// refs/remotes/origin/master
@@ -521,7 +1207,10 @@ fn resolve_base_refs(
     for base in bases {
         match repo.find_branch(base, BranchType::Local) {
             Ok(branch) => {
-                let refname = branch.get().name().context("non utf-8 base branch ref")?;
+                let refname = branch
+                    .get()
+                    .name()
+                    .ok_or_else(|| Error::non_utf8("base branch ref"))?;
                 result.insert((*base).to_string());
                 result.insert((*refname).to_string());
             }
@@ -555,7 +1244,9 @@ fn resolve_base_remote_refs(
 
         // match "origin/master -> refs/remotes/origin/master"
         if let Ok(remote_ref) = repo.find_reference(&format!("refs/remotes/{}", base)) {
-            let refname = remote_ref.name().context("non-utf8 reference name")?;
+            let refname = remote_ref
+                .name()
+                .ok_or_else(|| Error::non_utf8("reference name"))?;
             result.push(refname.to_string());
             continue;
         }
@@ -584,34 +1275,125 @@ fn resolve_base_remote_refs(
 /// -> refs/heads/release-v1.x
 /// refs/remotes/origin/release-*
 /// -> refs/remotes/origin/release-v1.x
+/// regex:release-v[0-9]+\.x
+/// -> same as release-*
+/// remote:origin/release-*
+/// -> refs/remotes/origin/release-v1.x
+///
+/// Patterns starting with '!' exclude what they resolve to from the others, regardless of the order.
 #[allow(clippy::implicit_hasher)]
 fn resolve_protected_refs(
     repo: &Repository,
     config: &GitConfig,
     protected_branches: &HashSet<&str>,
 ) -> Result<HashSet<String>> {
+    let mut protected = HashSet::new();
+    let mut excluded = HashSet::new();
+    for pattern in protected_branches {
+        if pattern.starts_with('!') {
+            let pattern = pattern.trim_start_matches('!');
+            excluded.extend(resolve_protected_pattern(repo, config, pattern)?);
+        } else {
+            protected.extend(resolve_protected_pattern(repo, config, pattern)?);
+        }
+    }
+    Ok(protected.difference(&excluded).cloned().collect())
+}
+
+fn resolve_protected_pattern(
+    repo: &Repository,
+    config: &GitConfig,
+    pattern: &str,
+) -> Result<HashSet<String>> {
+    // ':' can't be a part of refnames, so it is safe to use it as a separator.
+    let mut split = pattern.splitn(2, ':');
+    match (split.next(), split.next()) {
+        (Some("regex"), Some(regex)) => {
+            let regex = Regex::new(&format!("^(?:{})$", regex))
+                .map_err(|err| Error::config("trim.protected", err))?;
+            resolve_protected_refs_by(repo, config, |name| regex.is_match(name))
+        }
+        (Some("remote"), Some(remote_pattern)) => {
+            resolve_protected_remote_refs(repo, remote_pattern)
+        }
+        _ => {
+            let glob = Pattern::new(pattern).map_err(|err| Error::config("trim.protected", err))?;
+            resolve_protected_refs_by(repo, config, |name| glob.matches(name))
+        }
+    }
+}
+
+/// A ref is protected if the matcher matches the refname, or the name without 'refs/remotes/'
+/// for a remote ref. A local branch is protected with its upstream if the matcher matches
+/// the branch name.
+fn resolve_protected_refs_by<F>(
+    repo: &Repository,
+    config: &GitConfig,
+    matches: F,
+) -> Result<HashSet<String>>
+where
+    F: Fn(&str) -> bool,
+{
     let mut result = HashSet::default();
-    for protected in protected_branches {
-        for reference in repo.references_glob(protected)? {
-            let reference = reference?;
-            let refname = reference.name().context("non utf-8 refname")?;
+    for reference in repo.references()? {
+        let reference = reference?;
+        let refname = reference.name().ok_or_else(|| Error::non_utf8("refname"))?;
+        let is_remote_ref = refname.starts_with("refs/remotes/");
+        if matches(refname) || (is_remote_ref && matches(&refname["refs/remotes/".len()..])) {
             result.insert(refname.to_string());
         }
-        for reference in repo.references_glob(&format!("refs/remotes/{}", protected))? {
-            let reference = reference?;
-            let refname = reference.name().context("non utf-8 refname")?;
+    }
+    for branch in repo.branches(Some(BranchType::Local))? {
+        let (branch, _) = branch?;
+        let branch_name = branch
+            .name()?
+            .ok_or_else(|| Error::non_utf8("branch name"))?;
+        if matches(branch_name) {
+            result.insert(branch_name.to_string());
+            if let Some(remote_ref) = get_fetch_remote_ref(repo, config, branch_name)? {
+                result.insert(remote_ref);
+            }
+            let reference = branch.into_reference();
+            let refname = reference.name().ok_or_else(|| Error::non_utf8("ref"))?;
             result.insert(refname.to_string());
         }
-        for branch in repo.branches(Some(BranchType::Local))? {
-            let (branch, _) = branch?;
-            let branch_name = branch.name()?.context("non utf-8 branch name")?;
-            if Pattern::new(protected)?.matches(branch_name) {
-                result.insert(branch_name.to_string());
-                if let Some(remote_ref) = get_fetch_remote_ref(repo, config, branch_name)? {
-                    result.insert(remote_ref);
-                }
-                let reference = branch.into_reference();
-                let refname = reference.name().context("non utf-8 ref")?;
+    }
+    Ok(result)
+}
+
+/// `<remote>/<glob>` matches the remote refs that are fetched from the branches of the remote
+/// whose names on the remote match the glob, e.g. 'origin/release-*' matches
+/// 'refs/remotes/origin/release-v1.x' that is fetched from 'refs/heads/release-v1.x' of origin.
+fn resolve_protected_remote_refs(
+    repo: &Repository,
+    remote_pattern: &str,
+) -> Result<HashSet<String>> {
+    let mut split = remote_pattern.splitn(2, '/');
+    let (remote_name, glob) = match (split.next(), split.next()) {
+        (Some(remote_name), Some(glob)) => (
+            remote_name,
+            Pattern::new(glob).map_err(|err| Error::config("trim.protected", err))?,
+        ),
+        _ => {
+            return Err(Error::config(
+                "trim.protected",
+                format!(
+                    "'remote:{}' should be 'remote:<remote>/<glob>'",
+                    remote_pattern
+                ),
+            ))
+        }
+    };
+    let mut result = HashSet::default();
+    for reference in repo.references_glob("refs/remotes/*")? {
+        let reference = reference?;
+        let refname = reference.name().ok_or_else(|| Error::non_utf8("refname"))?;
+        if let Some(ref_on_remote) = get_ref_on_remote_from_remote_ref(repo, refname)? {
+            if ref_on_remote.remote_name != remote_name {
+                continue;
+            }
+            let name_on_remote = ref_on_remote.refname.trim_start_matches("refs/heads/");
+            if glob.matches(name_on_remote) || glob.matches(&ref_on_remote.refname) {
                 result.insert(refname.to_string());
             }
         }
@@ -619,19 +1401,27 @@ fn resolve_protected_refs(
     Ok(result)
 }
 
-pub fn delete_local_branches(repo: &Repository, branches: &[&str], dry_run: bool) -> Result<()> {
+/// Every branch is tried even if some of them fail. It only fails early when it can't detach HEAD.
+pub fn delete_local_branches(
+    repo: &Repository,
+    branches: &[&str],
+    dry_run: bool,
+) -> Result<DeletionReport> {
+    let mut report = DeletionReport::default();
     if branches.is_empty() {
-        return Ok(());
+        return Ok(report);
     }
 
-    let detach_to = if repo.head_detached()? {
+    let detach_to = if repo.is_bare() || repo.head_detached()? {
         None
     } else {
         let head = repo.head()?;
-        let head_refname = head.name().context("non-utf8 head ref name")?;
-        assert!(head_refname.starts_with("refs/heads/"));
-        let head_name = &head_refname["refs/heads/".len()..];
-        if branches.contains(&head_name) {
+        let head_refname = head
+            .name()
+            .ok_or_else(|| Error::non_utf8("head ref name"))?;
+        let is_head = head_refname.starts_with("refs/heads/")
+            && branches.contains(&&head_refname["refs/heads/".len()..]);
+        if is_head {
             Some(head)
         } else {
             None
@@ -641,29 +1431,72 @@ pub fn delete_local_branches(repo: &Repository, branches: &[&str], dry_run: bool
     if let Some(head) = detach_to {
         subprocess::checkout(repo, head, dry_run)?;
     }
-    subprocess::branch_delete(repo, branches, dry_run)?;
+    for branch in branches {
+        report.record(branch, subprocess::branch_delete(repo, branch, dry_run));
+    }
 
-    Ok(())
+    Ok(report)
 }
 
+/// Every remote ref is tried even if some of them fail.
+/// It only fails early before anything is pushed.
 pub fn delete_remote_branches(
     repo: &Repository,
     remote_refs: &[&str],
     dry_run: bool,
-) -> Result<()> {
-    if remote_refs.is_empty() {
-        return Ok(());
-    }
+) -> Result<DeletionReport> {
+    let mut report = DeletionReport::default();
     let mut per_remote = HashMap::new();
     for remote_ref in remote_refs {
-        let ref_on_remote = get_ref_on_remote_from_remote_ref(repo, remote_ref)?;
+        let ref_on_remote = match get_ref_on_remote_from_remote_ref(repo, remote_ref)? {
+            Some(ref_on_remote) => ref_on_remote,
+            None => {
+                report.record(
+                    remote_ref,
+                    Err(Error::MissingRemote(remote_ref.to_string())),
+                );
+                continue;
+            }
+        };
         let entry = per_remote
             .entry(ref_on_remote.remote_name)
             .or_insert_with(Vec::new);
-        entry.push(ref_on_remote.refname);
+        // The commit that it was classified with, which is expected to be on the remote.
+        let oid = repo.refname_to_id(remote_ref)?;
+        entry.push(((ref_on_remote.refname, oid), remote_ref.to_string()));
     }
-    for (remote_name, remote_refnames) in per_remote.iter() {
-        subprocess::push_delete(repo, remote_name, remote_refnames, dry_run)?;
+    for (remote_name, refs) in per_remote.iter() {
+        let expected: Vec<_> = refs.iter().map(|(expected, _)| expected.clone()).collect();
+        match subprocess::push_delete(repo, remote_name, &expected, dry_run) {
+            Ok(results) => {
+                for ((_, remote_ref), (_, result)) in refs.iter().zip(results) {
+                    report.record(remote_ref, result);
+                }
+            }
+            Err(err) => {
+                let message = err.to_string();
+                for (_, remote_ref) in refs {
+                    report.record(remote_ref, Err(Error::message(&message)));
+                }
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Delete remote refs only from the local repository like `git branch --delete --remotes`.
+/// Orphaned remote refs are always deleted in this way since there is no remote to push to.
+pub fn delete_remote_refs_locally(
+    repo: &Repository,
+    remote_refs: &[&str],
+    dry_run: bool,
+) -> DeletionReport {
+    let mut report = DeletionReport::default();
+    for remote_ref in remote_refs {
+        report.record(
+            remote_ref,
+            subprocess::update_ref_delete(repo, remote_ref, dry_run),
+        );
     }
-    Ok(())
+    report
 }
@@ -1,105 +1,646 @@
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
+use std::env;
 use std::iter::FromIterator;
+use std::path::{Path, PathBuf};
 
 use dialoguer::Confirmation;
-use git2::Repository;
+use git2::{Config as GitConfig, Repository};
 use log::*;
+use rayon::prelude::*;
 
-use git_trim::args::{Args, CommaSeparatedSet, DeleteFilter};
-use git_trim::{config, Config, Git};
-use git_trim::{delete_local_branches, delete_remote_branches, get_merged_or_gone, remote_update};
+use git_trim::archive::Archive;
+use git_trim::args::{Args, CommaSeparatedSet, DeleteFilter, OutputFormat};
+use git_trim::batch::{
+    discover_repositories, discover_submodules, read_repository_list, submodule_config,
+};
+use git_trim::deletion::DeletionReport;
+use git_trim::explain::explain;
+use git_trim::forge::{self, new_forge, Forge};
+use git_trim::interactive;
+use git_trim::journal::Journal;
+use git_trim::{config, message, Config, Git, MergedOrGone, MergedOrGoneAndKeptBacks};
+use git_trim::{
+    delete_local_branches, delete_remote_branches, delete_remote_refs_locally, get_merged_or_gone,
+    remote_update, reserve_stdout,
+};
 
-type Result<T> = ::std::result::Result<T, Error>;
-type Error = Box<dyn std::error::Error>;
+type Result<T> = ::std::result::Result<T, Failure>;
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Exit codes. They are documented in the README, so don't change them.
+mod exit_code {
+    pub const SUCCESS: i32 = 0;
+    /// Unexpected errors
+    pub const ERROR: i32 = 1;
+    /// Invalid `git config trim.*` or the options that override them
+    pub const CONFIG_ERROR: i32 = 2;
+    /// `--check` found branches to trim
+    pub const TRIMMABLE: i32 = 3;
+    pub const CANCELLED: i32 = 4;
+    /// Some of the branches might be deleted before the failure.
+    pub const PARTIAL_DELETION_FAILURE: i32 = 5;
+}
+
+/// An error with the exit code for it.
+struct Failure {
+    code: i32,
+    error: Error,
+}
+
+impl<E> From<E> for Failure
+where
+    E: Into<Error>,
+{
+    fn from(error: E) -> Self {
+        let error = error.into();
+        // An invalid config can be found deep in the library, e.g. an invalid `trim.protected`.
+        let code = match error.downcast_ref::<git_trim::Error>() {
+            Some(error) if error.is_config() => exit_code::CONFIG_ERROR,
+            _ => exit_code::ERROR,
+        };
+        Failure { code, error }
+    }
+}
+
+trait WithExitCode<T> {
+    fn with_exit_code(self, code: i32) -> Result<T>;
+}
+
+impl<T, E> WithExitCode<T> for ::std::result::Result<T, E>
+where
+    E: Into<Error>,
+{
+    fn with_exit_code(self, code: i32) -> Result<T> {
+        self.map_err(|error| Failure {
+            code,
+            error: error.into(),
+        })
+    }
+}
 
 #[paw::main]
-fn main(args: Args) -> Result<()> {
+fn main(args: Args) {
     env_logger::init();
     info!("SEMVER: {}", env!("VERGEN_SEMVER"));
     info!("SHA: {}", env!("VERGEN_SHA"));
     info!("COMMIT_DATE: {}", env!("VERGEN_COMMIT_DATE"));
     info!("TARGET_TRIPLE: {}", env!("VERGEN_TARGET_TRIPLE"));
 
+    let code = match run(args) {
+        Ok(code) => code,
+        Err(Failure { code, error }) => {
+            print_error("", &*error);
+            code
+        }
+    };
+    std::process::exit(code);
+}
+
+/// Prints the error and its causes, except the ones that are already a part of the message.
+fn print_error(prefix: &str, error: &(dyn std::error::Error + 'static)) {
+    let mut told = error.to_string();
+    eprintln!("Error: {}{}", prefix, told);
+    let mut source = error.source();
+    while let Some(cause) = source {
+        let message = cause.to_string();
+        if !told.contains(&message) {
+            eprintln!("Caused by: {}", message);
+            told.push_str(&message);
+        }
+        source = cause.source();
+    }
+}
+
+fn run(args: Args) -> Result<i32> {
+    if let OutputFormat::Json = args.format {
+        reserve_stdout();
+    }
+
+    if args.recursive.is_some() || args.repos.is_some() {
+        return run_batch(&args);
+    }
+
     let git = Git::try_from(Repository::open_from_env()?)?;
 
-    let bases = config::get(&git.config, "trim.bases")
-        .with_explicit("cli", flatten_collect(args.bases.clone()).into_option())
-        .with_default(&CommaSeparatedSet::from_iter(vec![
-            String::from("develop"),
-            String::from("master"),
-        ]))
-        .parse_flatten()?
-        .expect("has default");
-    let protected = config::get(&git.config, "trim.protected")
-        .with_explicit("cli", flatten_collect(args.protected.clone()).into_option())
-        .with_default(&CommaSeparatedSet::from_iter(bases.iter().cloned()))
-        .parse_flatten()?
-        .expect("has default");
-    let update = config::get(&git.config, "trim.update")
-        .with_explicit("cli", args.update())
-        .with_default(&true)
-        .read()?
-        .expect("has default");
-    let confirm = config::get(&git.config, "trim.confirm")
-        .with_explicit("cli", args.confirm())
-        .with_default(&true)
-        .read()?
-        .expect("has default");
-    let detach = config::get(&git.config, "trim.detach")
-        .with_explicit("cli", args.detach())
-        .with_default(&true)
-        .read()?
-        .expect("has default");
-    let filter = config::get(&git.config, "trim.delete")
-        .with_explicit("cli", flatten_collect(args.delete.clone()).into_option())
-        .with_default(&DeleteFilter::merged())
-        .parse_flatten()?
-        .expect("has default");
+    if let Some(run_id) = &args.undo {
+        let journal = Journal::load(&git.repo, run_id.as_ref().map(String::as_str))?;
+        journal.undo(&git.repo, args.undo_push, args.dry_run)?;
+        if !args.dry_run {
+            journal.remove(&git.repo)?;
+        }
+        return Ok(exit_code::SUCCESS);
+    }
+
+    let settings = Settings::load(&git.config, None, &args)?;
 
-    info!("bases: {:?}", bases);
-    info!("protected: {:?}", protected);
-    info!("update: {:?}", update);
-    info!("confirm: {:?}", confirm);
-    info!("detach: {:?}", detach);
-    info!("filter: {:?}", filter);
+    if settings.recurse_submodules && args.explain.is_none() {
+        let path = git.repo.workdir().unwrap_or_else(|| git.repo.path());
+        let targets = with_submodules(vec![path.to_path_buf()], &args);
+        // Without submodules, it is the same as trimming the repository alone,
+        // so that the output keeps the shape of a single repository.
+        if targets.len() > 1 {
+            return run_targets(&args, targets);
+        }
+    }
 
-    if *update {
+    if settings.update {
         remote_update(&git.repo, args.dry_run)?;
-        println!();
+        message!();
     }
 
-    let branches = get_merged_or_gone(
-        &git,
-        &Config {
-            bases: bases.iter().map(String::as_str).collect(),
-            protected_branches: protected.iter().map(String::as_str).collect(),
-            filter: filter.clone(),
+    if let Some(branch) = &args.explain {
+        print!("{}", explain(&git, &settings.config(), branch)?);
+        return Ok(exit_code::SUCCESS);
+    }
+
+    let branches = get_merged_or_gone(&git, &settings.config())?;
+
+    match args.format {
+        OutputFormat::Human => branches.print_summary(&git.repo)?,
+        OutputFormat::Json => branches.print_json(&git.repo)?,
+    }
+
+    if args.check {
+        return Ok(check(&branches));
+    }
+
+    trim(&git, &args, &settings, &branches, settings.confirm)
+}
+
+/// Settings of a repository from its `git config trim.*` and the options that override them.
+struct Settings {
+    bases: Vec<String>,
+    protected: Vec<String>,
+    update: bool,
+    confirm: bool,
+    detach: bool,
+    filter: DeleteFilter,
+    stale_after: Option<u64>,
+    forge: Option<Box<dyn Forge>>,
+    archive: Option<Archive>,
+    recurse_submodules: bool,
+}
+
+impl Settings {
+    /// Keys that are not in `git_config` are read from `fallback`, e.g. the superproject's config.
+    fn load(git_config: &GitConfig, fallback: Option<&GitConfig>, args: &Args) -> Result<Settings> {
+        let bases = config::get(git_config, "trim.bases")
+            .with_fallback(fallback)
+            .with_explicit("cli", flatten_collect(args.bases.clone()).into_option())
+            .with_default(&CommaSeparatedSet::from_iter(vec![
+                String::from("develop"),
+                String::from("master"),
+            ]))
+            .parse_flatten()
+            .with_exit_code(exit_code::CONFIG_ERROR)?
+            .expect("has default");
+        let protected = config::get(git_config, "trim.protected")
+            .with_fallback(fallback)
+            .with_explicit("cli", flatten_collect(args.protected.clone()).into_option())
+            .with_default(&CommaSeparatedSet::from_iter(bases.iter().cloned()))
+            .parse_flatten()
+            .with_exit_code(exit_code::CONFIG_ERROR)?
+            .expect("has default");
+        let update = config::get(git_config, "trim.update")
+            .with_fallback(fallback)
+            .with_explicit("cli", args.update()?)
+            .with_default(&true)
+            .read()
+            .with_exit_code(exit_code::CONFIG_ERROR)?
+            .expect("has default");
+        let confirm = config::get(git_config, "trim.confirm")
+            .with_fallback(fallback)
+            .with_explicit("cli", args.confirm()?)
+            .with_default(&true)
+            .read()
+            .with_exit_code(exit_code::CONFIG_ERROR)?
+            .expect("has default");
+        let detach = config::get(git_config, "trim.detach")
+            .with_fallback(fallback)
+            .with_explicit("cli", args.detach()?)
+            .with_default(&true)
+            .read()
+            .with_exit_code(exit_code::CONFIG_ERROR)?
+            .expect("has default");
+        let filter = config::get(git_config, "trim.delete")
+            .with_fallback(fallback)
+            .with_explicit("cli", flatten_collect(args.delete.clone()).into_option())
+            .with_default(&DeleteFilter::merged())
+            .parse_flatten()
+            .with_exit_code(exit_code::CONFIG_ERROR)?
+            .expect("has default");
+        let stale_after = config::get(git_config, "trim.staleAfter")
+            .with_fallback(fallback)
+            .with_explicit("cli", args.stale_after)
+            .parse()
+            .with_exit_code(exit_code::CONFIG_ERROR)?;
+        let forge_kind = config::get(git_config, "trim.forge")
+            .with_fallback(fallback)
+            .with_explicit("cli", args.forge)
+            .parse()
+            .with_exit_code(exit_code::CONFIG_ERROR)?;
+        let forge_url = config::get(git_config, "trim.forgeUrl")
+            .with_fallback(fallback)
+            .with_explicit("cli", args.forge_url.clone())
+            .read()
+            .with_exit_code(exit_code::CONFIG_ERROR)?;
+        let archive_kind = config::get(git_config, "trim.archive")
+            .with_fallback(fallback)
+            .with_explicit("cli", args.archive)
+            .parse()
+            .with_exit_code(exit_code::CONFIG_ERROR)?;
+        let archive_remote = config::get(git_config, "trim.archiveRemote")
+            .with_fallback(fallback)
+            .with_explicit("cli", args.archive_remote.clone())
+            .read()
+            .with_exit_code(exit_code::CONFIG_ERROR)?;
+        let recurse_submodules = config::get(git_config, "trim.recurseSubmodules")
+            .with_fallback(fallback)
+            .with_explicit("cli", args.recurse_submodules()?)
+            .with_default(&false)
+            .read()
+            .with_exit_code(exit_code::CONFIG_ERROR)?
+            .expect("has default");
+
+        info!("bases: {:?}", bases);
+        info!("protected: {:?}", protected);
+        info!("update: {:?}", update);
+        info!("confirm: {:?}", confirm);
+        info!("detach: {:?}", detach);
+        info!("filter: {:?}", filter);
+        info!("stale_after: {:?}", stale_after);
+        info!("forge: {:?}", forge_kind);
+        info!("forge_url: {:?}", forge_url);
+        info!("archive: {:?}", archive_kind);
+        info!("archive_remote: {:?}", archive_remote);
+        info!("recurse_submodules: {:?}", recurse_submodules);
+
+        let forge = match forge_kind {
+            Some(kind) => Some(
+                new_forge(
+                    *kind,
+                    forge_url.as_ref().map(|x| x.as_str()),
+                    env::var(forge::TOKEN_ENV).ok(),
+                )
+                .with_exit_code(exit_code::CONFIG_ERROR)?,
+            ),
+            None => None,
+        };
+        let archive = match archive_kind {
+            Some(kind) => Some(Archive::new(*kind, archive_remote.map(|x| x.unwrap()))?),
+            None => None,
+        };
+
+        Ok(Settings {
+            bases: bases.iter().cloned().collect(),
+            protected: protected.iter().cloned().collect(),
+            update: *update,
+            confirm: *confirm,
             detach: *detach,
-        },
-    )?;
+            filter: filter.unwrap(),
+            stale_after: stale_after.map(|x| *x),
+            forge,
+            archive,
+            recurse_submodules: *recurse_submodules,
+        })
+    }
 
-    branches.print_summary(&git.repo)?;
+    fn config(&self) -> Config<'_> {
+        Config {
+            bases: self.bases.iter().map(String::as_str).collect(),
+            protected_branches: self.protected.iter().map(String::as_str).collect(),
+            filter: self.filter.clone(),
+            detach: self.detach,
+            stale_after: self.stale_after,
+            forge: self.forge.as_ref().map(|x| x.as_ref()),
+        }
+    }
+}
 
-    let to_delete = branches.to_delete;
-    let any_branches_to_remove = !(to_delete.locals().is_empty() && to_delete.remotes().is_empty());
+fn check(branches: &MergedOrGoneAndKeptBacks) -> i32 {
+    if branches.to_delete.is_empty() {
+        exit_code::SUCCESS
+    } else {
+        exit_code::TRIMMABLE
+    }
+}
+
+/// Delete the branches of the plan after the selection and the confirmation.
+fn trim(
+    git: &Git,
+    args: &Args,
+    settings: &Settings,
+    branches: &MergedOrGoneAndKeptBacks,
+    confirm: bool,
+) -> Result<i32> {
+    let to_delete = if args.interactive {
+        interactive::select(&git.repo, branches)?
+    } else {
+        branches.to_delete.clone()
+    };
+    let remotes_to_delete_locally: Vec<_> = to_delete
+        .local_only_remotes()
+        .into_iter()
+        .chain(to_delete.orphans())
+        .collect();
+    let any_branches_to_remove = !to_delete.is_empty();
+    let archive_plan = settings.archive.as_ref().map(|x| x.plan(&to_delete));
+    if let (Some(archive), Some(plan), OutputFormat::Human) =
+        (&settings.archive, &archive_plan, args.format)
+    {
+        archive.print_plan(plan);
+    }
 
     if !args.dry_run
-        && *confirm
+        && !args.interactive
+        && confirm
         && any_branches_to_remove
         && !Confirmation::new()
             .with_text("Confirm?")
             .default(false)
             .interact()?
     {
-        println!("Cancelled");
-        return Ok(());
+        message!("Cancelled");
+        return Ok(exit_code::CANCELLED);
+    }
+
+    // The tips and the upstream configs are gone after the deletion, so it is taken before.
+    let journal = if args.dry_run {
+        None
+    } else {
+        Some(Journal::record(
+            &git.repo,
+            &git.config,
+            &to_delete.locals(),
+            &to_delete.remotes(),
+            &remotes_to_delete_locally,
+        )?)
+    };
+
+    if let (Some(archive), Some(plan)) = (&settings.archive, &archive_plan) {
+        archive.archive(&git.repo, plan, args.dry_run)?;
+    }
+
+    let mut report = DeletionReport::default();
+    let deleted = delete(
+        &git.repo,
+        &to_delete,
+        &remotes_to_delete_locally,
+        args.dry_run,
+        &mut report,
+    );
+
+    if let Some(mut journal) = journal {
+        journal.retain_deleted(&report);
+        if !journal.is_empty() {
+            journal.save(&git.repo)?;
+            message!(
+                "Recorded to the journal. You can restore them with `git trim --undo {}`",
+                journal.run_id
+            );
+        }
     }
+    deleted.with_exit_code(exit_code::PARTIAL_DELETION_FAILURE)?;
+
+    if !args.dry_run || !report.failed.is_empty() || !report.kept_back.is_empty() {
+        report.print();
+    }
+    report
+        .into_result()
+        .with_exit_code(exit_code::PARTIAL_DELETION_FAILURE)?;
+    Ok(exit_code::SUCCESS)
+}
 
-    delete_remote_branches(&git.repo, &to_delete.remotes(), args.dry_run)?;
-    delete_local_branches(&git.repo, &to_delete.locals(), args.dry_run)?;
+/// The report has what is deleted so far even if it fails in the middle.
+fn delete(
+    repo: &Repository,
+    to_delete: &MergedOrGone,
+    remotes_to_delete_locally: &[&str],
+    dry_run: bool,
+    report: &mut DeletionReport,
+) -> git_trim::Result<()> {
+    report.extend(delete_remote_branches(repo, &to_delete.remotes(), dry_run)?);
+    report.extend(delete_remote_refs_locally(
+        repo,
+        remotes_to_delete_locally,
+        dry_run,
+    ));
+    report.extend(delete_local_branches(repo, &to_delete.locals(), dry_run)?);
     Ok(())
 }
 
+/// A repository to trim together with the others.
+struct Target {
+    path: PathBuf,
+    /// The immediate superproject that the submodule reads `trim.*` from if it doesn't have its own.
+    superproject: Option<PathBuf>,
+}
+
+/// A repository of `--recursive`, `--repos` or `--recurse-submodules` with its plan.
+struct Planned {
+    git: Git,
+    settings: Settings,
+    branches: MergedOrGoneAndKeptBacks,
+}
+
+fn run_batch(args: &Args) -> Result<i32> {
+    let paths = match (&args.recursive, &args.repos) {
+        (Some(dir), _) => discover_repositories(dir)?,
+        (None, Some(file)) => read_repository_list(file)?,
+        (None, None) => unreachable!("it is not a batch"),
+    };
+    info!("repositories: {:?}", paths);
+    run_targets(args, with_submodules(paths, args))
+}
+
+/// Append the initialized submodules of the repositories that recurse into them.
+/// A repository that fails here is left alone, so that it fails on its plan with the others.
+fn with_submodules(paths: Vec<PathBuf>, args: &Args) -> Vec<Target> {
+    let mut targets = Vec::new();
+    for path in paths {
+        let submodules = match find_submodules(&path, args) {
+            Ok(submodules) => submodules,
+            Err(Failure { error, .. }) => {
+                warn!("Failed to find submodules of {:?}: {}", path, error);
+                Vec::new()
+            }
+        };
+        info!("submodules of {:?}: {:?}", path, submodules);
+        targets.push(Target {
+            path: path.clone(),
+            superproject: None,
+        });
+        targets.extend(
+            submodules
+                .into_iter()
+                .map(|(submodule, superproject)| Target {
+                    path: submodule,
+                    superproject: Some(superproject),
+                }),
+        );
+    }
+    targets
+}
+
+fn find_submodules(path: &Path, args: &Args) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let repo = Repository::open(path)?;
+    let recurse_submodules = config::get(&repo.config()?, "trim.recurseSubmodules")
+        .with_explicit("cli", args.recurse_submodules()?)
+        .with_default(&false)
+        .read()?
+        .expect("has default");
+    if *recurse_submodules {
+        Ok(discover_submodules(&repo)?)
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+/// Plan every repository in parallel, print the summaries together, and trim them one by one.
+/// A failure of a repository is reported at the end without stopping the others,
+/// and the exit code is the first non-zero code of the repositories.
+fn run_targets(args: &Args, targets: Vec<Target>) -> Result<i32> {
+    // `Git` can't be sent between threads, so it is opened again for each plan.
+    let results: Vec<(PathBuf, Result<Planned>)> = targets
+        .into_par_iter()
+        .map(|target| {
+            let planned = plan(&target, args);
+            (target.path, planned)
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+        .map(|(path, planned)| {
+            let planned = planned.and_then(|(settings, branches)| {
+                Ok(Planned {
+                    git: Git::try_from(Repository::open(&path)?)?,
+                    settings,
+                    branches,
+                })
+            });
+            (path, planned)
+        })
+        .collect();
+
+    let mut codes: Vec<(String, i32)> = Vec::new();
+    let mut reports = BTreeMap::new();
+    for (path, planned) in &results {
+        let name = path.display().to_string();
+        match (planned, args.format) {
+            (Ok(planned), OutputFormat::Human) => {
+                println!("Repository {}:", name);
+                planned.branches.print_summary(&planned.git.repo)?;
+                println!();
+            }
+            (Ok(planned), OutputFormat::Json) => {
+                reports.insert(name, planned.branches.report(&planned.git.repo)?);
+            }
+            (Err(Failure { code, error }), _) => {
+                let code = *code;
+                print_error(&format!("{}: ", name), &**error);
+                codes.push((name, code));
+            }
+        }
+    }
+    if let OutputFormat::Json = args.format {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    }
+
+    if args.check {
+        let trimmable = results.iter().any(|(_, planned)| match planned {
+            Ok(planned) => check(&planned.branches) == exit_code::TRIMMABLE,
+            Err(_) => false,
+        });
+        let code = if trimmable {
+            exit_code::TRIMMABLE
+        } else {
+            exit_code::SUCCESS
+        };
+        return Ok(first_failure(&codes).unwrap_or(code));
+    }
+
+    let planned: Vec<_> = results
+        .iter()
+        .filter_map(|(path, planned)| planned.as_ref().ok().map(|x| (path, x)))
+        .collect();
+    let any_branches_to_remove = planned
+        .iter()
+        .any(|(_, x)| x.settings.confirm && !x.branches.to_delete.is_empty());
+    if !args.dry_run
+        && !args.interactive
+        && !args.confirm_each_repo
+        && any_branches_to_remove
+        && !Confirmation::new()
+            .with_text("Confirm for all repositories?")
+            .default(false)
+            .interact()?
+    {
+        message!("Cancelled");
+        return Ok(exit_code::CANCELLED);
+    }
+
+    for (path, planned) in planned {
+        let name = path.display().to_string();
+        if planned.branches.to_delete.is_empty() && !args.interactive {
+            continue;
+        }
+        message!("Repository {}:", name);
+        let confirm = args.confirm_each_repo && planned.settings.confirm;
+        match trim(
+            &planned.git,
+            args,
+            &planned.settings,
+            &planned.branches,
+            confirm,
+        ) {
+            Ok(code) => codes.push((name, code)),
+            Err(Failure { code, error }) => {
+                print_error(&format!("{}: ", name), &*error);
+                codes.push((name, code));
+            }
+        }
+    }
+
+    let failures: Vec<_> = codes
+        .iter()
+        .filter(|(_, code)| *code != exit_code::SUCCESS)
+        .collect();
+    if !failures.is_empty() {
+        message!("Repositories that are not trimmed completely:");
+        for (name, code) in &failures {
+            message!("  - {} (exit code {})", name, code);
+        }
+    }
+    Ok(first_failure(&codes).unwrap_or(exit_code::SUCCESS))
+}
+
+fn plan(target: &Target, args: &Args) -> Result<(Settings, MergedOrGoneAndKeptBacks)> {
+    let git = Git::try_from(Repository::open(&target.path)?)?;
+    let settings = match &target.superproject {
+        Some(superproject) => {
+            let superproject = Repository::open(superproject)?;
+            Settings::load(
+                &submodule_config(&git.repo)?,
+                Some(&superproject.config()?.snapshot()?),
+                args,
+            )?
+        }
+        None => Settings::load(&git.config, None, args)?,
+    };
+    if settings.update {
+        remote_update(&git.repo, args.dry_run)?;
+    }
+    let branches = get_merged_or_gone(&git, &settings.config())?;
+    Ok((settings, branches))
+}
+
+fn first_failure(codes: &[(String, i32)]) -> Option<i32> {
+    codes
+        .iter()
+        .map(|(_, code)| *code)
+        .find(|code| *code != exit_code::SUCCESS)
+}
+
 fn flatten_collect<I, C, T>(iter: I) -> C
 where
     I: IntoIterator<Item = C>,
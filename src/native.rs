@@ -0,0 +1,134 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+
+use crate::error::Result;
+use git2::{Commit, Diff, DiffFormat, ErrorCode, Oid, Repository, Tree};
+
+/// Which test tells that the branch is merged.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum MergedBy {
+    /// Every commit of the branch is applied to the base, like a merge or a rebase merge.
+    CherryPick,
+    /// The cumulative patch of the branch is applied to the base as a single commit.
+    Squash,
+}
+
+/// In-process counterpart of `subprocess::is_merged`.
+/// It never writes any object into the object database.
+pub fn is_merged(repo: &Repository, base_remote_ref: &str, branch: &str) -> Result<bool> {
+    Ok(merged_by(repo, base_remote_ref, branch)?.is_some())
+}
+
+/// Same as `is_merged`, but tells which test it is merged by.
+pub fn merged_by(
+    repo: &Repository,
+    base_remote_ref: &str,
+    branch: &str,
+) -> Result<Option<MergedBy>> {
+    let base = repo.revparse_single(base_remote_ref)?.peel_to_commit()?;
+    let branch = repo.revparse_single(branch)?.peel_to_commit()?;
+    let merge_base = match repo.merge_base(base.id(), branch.id()) {
+        Ok(merge_base) => repo.find_commit(merge_base)?,
+        Err(err) if err.code() == ErrorCode::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    // Patches that are applied to the base since the branch is forked.
+    let upstream_patch_ids = patch_ids(repo, base.id(), merge_base.id())?;
+    if is_merged_by_cherry(repo, &upstream_patch_ids, &base, &branch)? {
+        Ok(Some(MergedBy::CherryPick))
+    } else if is_squash_merged(repo, &upstream_patch_ids, &merge_base, &branch)? {
+        Ok(Some(MergedBy::Squash))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Same as `git rev-list --cherry-pick --right-only --no-merges base...branch` is empty.
+fn is_merged_by_cherry(
+    repo: &Repository,
+    upstream_patch_ids: &HashSet<u64>,
+    base: &Commit,
+    branch: &Commit,
+) -> Result<bool> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(branch.id())?;
+    revwalk.hide(base.id())?;
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        if commit.parent_count() > 1 {
+            continue;
+        }
+        if !upstream_patch_ids.contains(&commit_patch_id(repo, &commit)?) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Is the cumulative patch of the branch applied to the base as a single commit?
+/// It is the in-memory version of https://stackoverflow.com/a/56026209
+fn is_squash_merged(
+    repo: &Repository,
+    upstream_patch_ids: &HashSet<u64>,
+    merge_base: &Commit,
+    branch: &Commit,
+) -> Result<bool> {
+    let diff = repo.diff_tree_to_tree(Some(&merge_base.tree()?), Some(&branch.tree()?), None)?;
+    Ok(upstream_patch_ids.contains(&patch_id(&diff)?))
+}
+
+fn patch_ids(repo: &Repository, from: Oid, hide: Oid) -> Result<HashSet<u64>> {
+    let mut result = HashSet::new();
+    let mut revwalk = repo.revwalk()?;
+    revwalk.push(from)?;
+    revwalk.hide(hide)?;
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        if commit.parent_count() > 1 {
+            continue;
+        }
+        result.insert(commit_patch_id(repo, &commit)?);
+    }
+    Ok(result)
+}
+
+fn commit_patch_id(repo: &Repository, commit: &Commit) -> Result<u64> {
+    let parent_tree: Option<Tree> = if commit.parent_count() == 0 {
+        None
+    } else {
+        Some(commit.parent(0)?.tree()?)
+    };
+    let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+    patch_id(&diff)
+}
+
+/// Similar to `git patch-id`. Line numbers and whitespaces are ignored.
+/// It is only compared with the ones that are computed in the same process.
+fn patch_id(diff: &Diff) -> Result<u64> {
+    let mut hasher = DefaultHasher::new();
+    diff.print(DiffFormat::Patch, |delta, _hunk, line| {
+        match line.origin() {
+            'F' => {
+                delta.old_file().path().hash(&mut hasher);
+                delta.new_file().path().hash(&mut hasher);
+            }
+            'B' => {
+                delta.old_file().id().hash(&mut hasher);
+                delta.new_file().id().hash(&mut hasher);
+            }
+            'H' => {}
+            origin => {
+                origin.hash(&mut hasher);
+                for byte in line.content() {
+                    if !byte.is_ascii_whitespace() {
+                        byte.hash(&mut hasher);
+                    }
+                }
+            }
+        }
+        true
+    })?;
+    Ok(hasher.finish())
+}
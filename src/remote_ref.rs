@@ -1,4 +1,7 @@
-use anyhow::{Context, Result};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
 use git2::{BranchType, Config, Direction, Repository};
 use log::*;
 
@@ -24,7 +27,6 @@ fn get_remote_ref(
     remote_name: &str,
     branch: &str,
 ) -> Result<Option<String>> {
-    let remote = repo.find_remote(remote_name)?;
     let key = format!("branch.{}.merge", branch);
     let ref_on_remote: ConfigValue<String> =
         if let Some(ref_on_remote) = config::get(config, &key).read()? {
@@ -32,15 +34,30 @@ fn get_remote_ref(
         } else {
             return Ok(None);
         };
-    assert!(
-        ref_on_remote.starts_with("refs/"),
-        "'git config branch.{}.merge' should start with 'refs/'",
-        branch
-    );
+    if !ref_on_remote.starts_with("refs/") {
+        return Err(Error::config(&key, "It should start with 'refs/'"));
+    }
+    get_remote_ref_from_ref_on_remote(
+        repo,
+        config,
+        &RefOnRemote {
+            remote_name: remote_name.to_string(),
+            refname: ref_on_remote.to_string(),
+        },
+    )
+}
 
+/// The remote ref that the ref on the remote is fetched into, if it exists.
+/// refs/heads/master on origin -> refs/remotes/origin/master
+pub fn get_remote_ref_from_ref_on_remote(
+    repo: &Repository,
+    config: &Config,
+    ref_on_remote: &RefOnRemote,
+) -> Result<Option<String>> {
     if let Some(expanded) = expand_refspec(
-        &remote,
-        &ref_on_remote,
+        config,
+        &ref_on_remote.remote_name,
+        &ref_on_remote.refname,
         Direction::Fetch,
         ExpansionSide::Right,
     )? {
@@ -64,56 +81,81 @@ pub fn get_push_remote_ref(
     config: &Config,
     branch: &str,
 ) -> Result<Option<String>> {
-    if let Some(RefOnRemote {
-        remote_name,
-        refname,
-    }) = get_push_ref_on_remote(repo, config, branch)?
-    {
-        if let Some(remote_ref) = get_remote_ref(repo, config, &remote_name, &refname)? {
-            return Ok(Some(remote_ref));
-        }
+    match push_destination(repo, config, branch)? {
+        // The push upstream of `push.default=simple|upstream` is the fetch upstream,
+        // so it is not reported again as a push upstream.
+        Some((_, PushDestination::Upstream)) | None => Ok(None),
+        Some((ref_on_remote, _)) => get_remote_ref_from_ref_on_remote(repo, config, &ref_on_remote),
     }
-    Ok(None)
 }
 
-#[derive(Eq, PartialEq, Clone)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub struct RefOnRemote {
     pub remote_name: String,
     pub refname: String,
 }
 
-fn get_push_ref_on_remote(
+/// The branch on the remote that `git push` pushes the branch to.
+pub fn get_push_ref_on_remote(
     repo: &Repository,
     config: &Config,
     branch: &str,
 ) -> Result<Option<RefOnRemote>> {
+    Ok(push_destination(repo, config, branch)?.map(|(ref_on_remote, _)| ref_on_remote))
+}
+
+/// Where the push destination comes from.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum PushDestination {
+    Refspec,
+    SameName,
+    Upstream,
+}
+
+fn push_destination(
+    repo: &Repository,
+    config: &Config,
+    branch: &str,
+) -> Result<Option<(RefOnRemote, PushDestination)>> {
     let remote_name = config::get_push_remote(config, branch)?;
 
-    let remote = repo.find_remote(&remote_name)?;
     let reference = repo
         .find_branch(branch, BranchType::Local)?
         .into_reference();
-    let refname = reference.name().context("non utf-8 refname")?;
-    if let Some(push_on_remote) =
-        expand_refspec(&remote, refname, Direction::Push, ExpansionSide::Right)?
-    {
-        return Ok(Some(RefOnRemote {
-            remote_name: remote_name.to_string(),
-            refname: push_on_remote,
-        }));
+    let refname = reference.name().ok_or_else(|| Error::non_utf8("refname"))?;
+    if let Some(push_on_remote) = expand_refspec(
+        config,
+        &remote_name,
+        refname,
+        Direction::Push,
+        ExpansionSide::Right,
+    )? {
+        return Ok(Some((
+            RefOnRemote {
+                remote_name: remote_name.to_string(),
+                refname: push_on_remote,
+            },
+            PushDestination::Refspec,
+        )));
     }
 
     let push_default = config::get(config, "push.default")
-        .with_default(&String::from("simple"))
-        .read()?
+        .with_default(&PushDefault::Simple)
+        .parse()?
         .expect("has default");
 
-    match push_default.as_str() {
-        "current" => Ok(Some(RefOnRemote {
-            remote_name: remote_name.to_string(),
-            refname: branch.to_string(),
-        })),
-        "upstream" | "tracking" | "simple" => {
+    match *push_default {
+        PushDefault::Nothing => Ok(None),
+        // `matching` pushes every branch to the branch of the same name on the remote,
+        // which is the same destination as `current` for a single branch.
+        PushDefault::Current | PushDefault::Matching => Ok(Some((
+            RefOnRemote {
+                remote_name: remote_name.to_string(),
+                refname: refname.to_string(),
+            },
+            PushDestination::SameName,
+        ))),
+        PushDefault::Upstream | PushDefault::Simple => {
             if let Some(merge) = config::get(config, &format!("branch.{}.merge", branch))
                 .parse_with(|ref_on_remote| {
                     Ok(RefOnRemote {
@@ -122,35 +164,80 @@ fn get_push_ref_on_remote(
                     })
                 })?
             {
-                Ok(Some(merge.clone()))
+                Ok(Some((merge.clone(), PushDestination::Upstream)))
             } else {
                 warn!("The current branch {} has no upstream branch.", branch);
                 Ok(None)
             }
         }
-        "nothing" | "matching" => {
-            unimplemented!("push.default=nothing|matching is not implemented.")
+    }
+}
+
+/// https://git-scm.com/docs/git-config#Documentation/git-config.txt-pushdefault
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum PushDefault {
+    Nothing,
+    Current,
+    Upstream,
+    Simple,
+    Matching,
+}
+
+impl FromStr for PushDefault {
+    type Err = PushDefaultParseError;
+
+    fn from_str(value: &str) -> Result<PushDefault, Self::Err> {
+        match value {
+            "nothing" => Ok(PushDefault::Nothing),
+            "current" => Ok(PushDefault::Current),
+            "upstream" | "tracking" => Ok(PushDefault::Upstream),
+            "simple" => Ok(PushDefault::Simple),
+            "matching" => Ok(PushDefault::Matching),
+            _ => Err(PushDefaultParseError {
+                message: format!("Unexpected config push.default: {}", value),
+            }),
         }
-        _ => panic!("unexpected config push.default"),
     }
 }
 
+#[derive(Debug)]
+pub struct PushDefaultParseError {
+    message: String,
+}
+
+impl Display for PushDefaultParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PushDefaultParseError: {}", &self.message)
+    }
+}
+
+impl std::error::Error for PushDefaultParseError {}
+
+/// It returns `None` if the remote ref is orphaned, which means that no fetch refspec of
+/// any remote can make it, e.g. the remote is removed or renamed, or its refspec is changed.
+/// It also returns `None` if it is not a remote ref at all.
 pub fn get_ref_on_remote_from_remote_ref(
     repo: &Repository,
     remote_ref: &str,
-) -> Result<RefOnRemote> {
-    assert!(remote_ref.starts_with("refs/remotes/"));
+) -> Result<Option<RefOnRemote>> {
+    if !remote_ref.starts_with("refs/remotes/") {
+        return Ok(None);
+    }
+    let config = repo.config()?.snapshot()?;
     for remote_name in repo.remotes()?.iter() {
-        let remote_name = remote_name.context("non-utf8 remote name")?;
-        let remote = repo.find_remote(&remote_name)?;
-        if let Some(expanded) =
-            expand_refspec(&remote, remote_ref, Direction::Fetch, ExpansionSide::Left)?
-        {
-            return Ok(RefOnRemote {
-                remote_name: remote.name().context("non-utf8 remote name")?.to_string(),
+        let remote_name = remote_name.ok_or_else(|| Error::non_utf8("remote name"))?;
+        if let Some(expanded) = expand_refspec(
+            &config,
+            remote_name,
+            remote_ref,
+            Direction::Fetch,
+            ExpansionSide::Left,
+        )? {
+            return Ok(Some(RefOnRemote {
+                remote_name: remote_name.to_string(),
                 refname: expanded,
-            });
+            }));
         }
     }
-    unreachable!("matching refspec is not found");
+    Ok(None)
 }
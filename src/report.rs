@@ -0,0 +1,178 @@
+use std::fmt::{Display, Formatter};
+
+use crate::error::{Error, Result};
+use git2::{BranchType, Repository};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::forge::PullRequestState;
+use crate::{Classification, MergedOrGoneAndKeptBacks};
+
+#[derive(Serialize, Eq, PartialEq, Debug)]
+pub struct Report {
+    pub local_branches: Vec<RefReport>,
+    pub remote_refs: Vec<RefReport>,
+}
+
+#[derive(Serialize, Eq, PartialEq, Debug)]
+pub struct RefReport {
+    pub name: String,
+    pub refname: String,
+    pub status: Status,
+    pub reason: Option<String>,
+    /// The base remote ref that the decision was made against
+    pub base: Option<String>,
+    pub fetch: Option<String>,
+    pub push: Option<String>,
+    pub pull_request: Option<PullRequestState>,
+    /// The remote ref is deleted only from the local repository without pushing.
+    pub local_only: bool,
+}
+
+#[derive(Serialize, Eq, PartialEq, Ord, PartialOrd, Debug, Copy, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    MergedLocal,
+    GoneLocal,
+    MergedRemote,
+    GoneRemote,
+    StaleLocal,
+    StaleRemote,
+    OrphanedRemote,
+    KeptBack,
+    Remain,
+}
+
+/// The same name as in the JSON, e.g. `merged-local`.
+impl Display for Status {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match serde_json::to_value(self) {
+            Ok(Value::String(name)) => f.write_str(&name),
+            _ => Err(std::fmt::Error),
+        }
+    }
+}
+
+impl MergedOrGoneAndKeptBacks {
+    /// Every local branch and remote ref with the decision that is made for it.
+    pub fn report(&self, repo: &Repository) -> Result<Report> {
+        let mut local_branches = Vec::new();
+        for branch in repo.branches(Some(BranchType::Local))? {
+            let (branch, _) = branch?;
+            let name = branch
+                .name()?
+                .ok_or_else(|| Error::non_utf8("local branch name"))?;
+            let refname = branch
+                .get()
+                .name()
+                .ok_or_else(|| Error::non_utf8("local branch ref"))?;
+
+            let status = if self.to_delete.merged_locals.contains(name) {
+                Status::MergedLocal
+            } else if self.to_delete.gone_locals.contains(name) {
+                Status::GoneLocal
+            } else if self.to_delete.stale_locals.contains(name) {
+                Status::StaleLocal
+            } else if self.kept_back.contains_key(name) || self.kept_back.contains_key(refname) {
+                Status::KeptBack
+            } else {
+                Status::Remain
+            };
+            let classification = self.find_classification(|c| {
+                c.branch_name == name
+                    && (c.result.merged_locals.contains(name)
+                        || c.result.gone_locals.contains(name)
+                        || c.result.stale_locals.contains(name))
+            });
+            let classification =
+                classification.or_else(|| self.find_classification(|c| c.branch_name == name));
+            local_branches.push(self.ref_report(
+                name,
+                refname,
+                status,
+                &[name, refname],
+                classification,
+            ));
+        }
+
+        let mut remote_refs = Vec::new();
+        for branch in repo.branches(Some(BranchType::Remote))? {
+            let (branch, _) = branch?;
+            let name = branch
+                .name()?
+                .ok_or_else(|| Error::non_utf8("remote ref name"))?;
+            let refname = branch
+                .get()
+                .name()
+                .ok_or_else(|| Error::non_utf8("remote ref"))?;
+
+            let status = if self.to_delete.merged_remotes.contains(refname) {
+                Status::MergedRemote
+            } else if self.to_delete.gone_remotes.contains(refname) {
+                Status::GoneRemote
+            } else if self.to_delete.stale_remotes.contains(refname) {
+                Status::StaleRemote
+            } else if self.to_delete.orphaned_remotes.contains(refname) {
+                Status::OrphanedRemote
+            } else if self.kept_back.contains_key(refname) {
+                Status::KeptBack
+            } else {
+                Status::Remain
+            };
+            let classification = self.find_classification(|c| {
+                c.result.merged_remotes.contains(refname)
+                    || c.result.gone_remotes.contains(refname)
+                    || c.result.stale_remotes.contains(refname)
+            });
+            remote_refs.push(self.ref_report(name, refname, status, &[refname], classification));
+        }
+
+        Ok(Report {
+            local_branches,
+            remote_refs,
+        })
+    }
+
+    pub fn print_json(&self, repo: &Repository) -> Result<()> {
+        let report = self.report(repo)?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        Ok(())
+    }
+
+    fn find_classification<F>(&self, predicate: F) -> Option<&Classification>
+    where
+        F: Fn(&Classification) -> bool,
+    {
+        self.classifications.iter().find(|c| predicate(c))
+    }
+
+    fn ref_report(
+        &self,
+        name: &str,
+        refname: &str,
+        status: Status,
+        kept_back_keys: &[&str],
+        classification: Option<&Classification>,
+    ) -> RefReport {
+        let kept_back_reason = kept_back_keys
+            .iter()
+            .filter_map(|key| self.kept_back.get(*key))
+            .next();
+        let reason = match (status, kept_back_reason) {
+            (Status::KeptBack, Some(reason)) => Some(reason.clone()),
+            _ => classification.map(|c| c.message.to_string()),
+        };
+        RefReport {
+            name: name.to_string(),
+            refname: refname.to_string(),
+            status,
+            reason,
+            base: classification.map(|c| c.base.clone()),
+            fetch: classification.and_then(|c| c.fetch.clone()),
+            push: classification.and_then(|c| c.push.clone()),
+            pull_request: classification.and_then(|c| c.pull_request),
+            local_only: self.to_delete.local_only.contains(refname)
+                || self.to_delete.orphaned_remotes.contains(refname),
+        }
+    }
+}
@@ -1,73 +1,158 @@
 use std::iter::Iterator;
 
-use anyhow::{Context, Result};
-use git2::{Direction, Remote};
+use crate::error::{Error, Result};
+use git2::{Config, Direction};
 use log::*;
 
+use crate::config::{self, ConfigValue};
+
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub enum ExpansionSide {
     Right,
     Left,
 }
 
+/// `[+]<src>:<dst>` or `^<src>`
+/// https://git-scm.com/docs/git-fetch#Documentation/git-fetch.txt-ltrefspecgt
+struct Refspec<'a> {
+    src: &'a str,
+    dst: Option<&'a str>,
+    negative: bool,
+}
+
+impl<'a> Refspec<'a> {
+    fn parse(refspec: &'a str) -> Refspec<'a> {
+        if refspec.starts_with('^') {
+            return Refspec {
+                src: refspec.trim_start_matches('^'),
+                dst: None,
+                negative: true,
+            };
+        }
+        let mut split = refspec.trim_start_matches('+').splitn(2, ':');
+        Refspec {
+            src: split.next().unwrap_or_default(),
+            dst: split.next(),
+            negative: false,
+        }
+    }
+}
+
+/// Refspecs are read from the git config rather than from `git2::Remote`,
+/// since libgit2 refuses to load a remote with negative refspecs.
+/// Every refspec of the direction is tried in order, and the first one that matches wins,
+/// unless the ref on the remote is excluded by a negative refspec.
 pub fn expand_refspec(
-    remote: &Remote,
+    config: &Config,
+    remote_name: &str,
     reference: &str,
     direction: Direction,
     side: ExpansionSide,
 ) -> Result<Option<String>> {
-    for refspec in remote.refspecs() {
-        let left = refspec.src().context("non-utf8 src dst")?;
-        let right = refspec.dst().context("non-utf8 refspec dst")?;
-        // TODO: Why there isn't derive(Eq, PartialEq)?
-        match (direction, refspec.direction()) {
-            (Direction::Fetch, Direction::Push) | (Direction::Push, Direction::Fetch) => continue,
-            _ => {}
-        }
-        match side {
-            ExpansionSide::Right => return Ok(expand(left, right, &reference)),
-            ExpansionSide::Left => return Ok(expand(right, left, &reference)),
+    let key = match direction {
+        Direction::Fetch => format!("remote.{}.fetch", remote_name),
+        Direction::Push => format!("remote.{}.push", remote_name),
+    };
+    let refspecs = config::get::<Vec<String>>(config, &key)
+        .read()?
+        .map(ConfigValue::unwrap)
+        .unwrap_or_default();
+    let refspecs: Vec<_> = refspecs.iter().map(|x| Refspec::parse(x)).collect();
+
+    let is_excluded = |ref_on_remote: &str| {
+        refspecs
+            .iter()
+            .filter(|refspec| refspec.negative)
+            .any(|refspec| glob_match(refspec.src, ref_on_remote).is_some())
+    };
+
+    for refspec in refspecs.iter().filter(|refspec| !refspec.negative) {
+        let dst = match refspec.dst {
+            Some(dst) if !dst.is_empty() => dst,
+            _ => continue,
+        };
+        let expanded = match side {
+            ExpansionSide::Right => expand(refspec.src, dst, reference)?,
+            ExpansionSide::Left => expand(dst, refspec.src, reference)?,
         };
+        if let Some(expanded) = expanded {
+            let ref_on_remote = match side {
+                ExpansionSide::Right => reference,
+                ExpansionSide::Left => &expanded,
+            };
+            if is_excluded(ref_on_remote) {
+                trace!(
+                    "{} is excluded by a negative refspec of {}",
+                    ref_on_remote,
+                    key
+                );
+                return Ok(None);
+            }
+            return Ok(Some(expanded));
+        }
     }
     Ok(None)
 }
 
-fn expand(src: &str, dest: &str, reference: &str) -> Option<String> {
-    let src_stars = src.chars().filter(|&c| c == '*').count();
-    let dst_stars = dest.chars().filter(|&c| c == '*').count();
-    assert!(
-        src_stars <= 1 && src_stars == dst_stars,
-        "Unsupported refspec patterns: {}:{}",
-        src,
-        dest
-    );
+fn expand(src: &str, dest: &str, reference: &str) -> Result<Option<String>> {
+    let src_stars = src.matches('*').count();
+    let dst_stars = dest.matches('*').count();
+    if src_stars != dst_stars {
+        return Err(Error::UnsupportedRefspec(format!("{}:{}", src, dest)));
+    }
+
+    let matched = match glob_match(src, reference) {
+        Some(matched) => matched,
+        None => return Ok(None),
+    };
+    let mut result = String::new();
+    let mut parts = dest.split('*');
+    result.push_str(parts.next().unwrap_or_default());
+    for (part, matched) in parts.zip(matched) {
+        result.push_str(matched);
+        result.push_str(part);
+    }
+    Ok(Some(result))
+}
 
-    if let Some(matched) = simple_match(src, reference) {
-        Some(dest.replace("*", matched))
+/// Match the reference against the pattern, and return the substrings that each `*` matches.
+/// A `*` matches any string including `/`, so it can be in the middle of a path.
+fn glob_match<'a>(pattern: &str, reference: &'a str) -> Option<Vec<&'a str>> {
+    let mut parts = pattern.split('*');
+    let head = parts.next().unwrap_or_default();
+    if reference.get(..head.len()) != Some(head) {
+        return None;
+    }
+    let rest: Vec<_> = parts.collect();
+    let mut matched = Vec::new();
+    if match_rest(&rest, &reference[head.len()..], &mut matched) {
+        Some(matched)
     } else {
         None
     }
 }
 
-fn simple_match<'a>(pattern: &str, reference: &'a str) -> Option<&'a str> {
-    let src_stars = pattern.chars().filter(|&c| c == '*').count();
-    if src_stars <= 1 {
-        if let Some(star) = pattern.find('*') {
-            let left = &pattern[..star];
-            let right = &pattern[star + 1..];
-            if reference.starts_with(&left) && reference.ends_with(right) {
-                let matched = &reference[left.len()..reference.len() - right.len()];
-                return Some(matched);
+/// Each part follows a `*`. It backtracks when the shortest match of a `*` fails.
+fn match_rest<'a>(parts: &[&str], reference: &'a str, matched: &mut Vec<&'a str>) -> bool {
+    let (part, parts) = match parts.split_first() {
+        Some(x) => x,
+        None => return reference.is_empty(),
+    };
+    if parts.is_empty() {
+        return match reference.len().checked_sub(part.len()) {
+            Some(index) if reference.get(index..) == Some(part) => {
+                matched.push(&reference[..index]);
+                true
             }
-        } else if pattern == reference {
-            return Some("");
+            _ => false,
+        };
+    }
+    for (index, _) in reference.match_indices(part) {
+        matched.push(&reference[..index]);
+        if match_rest(parts, &reference[index + part.len()..], matched) {
+            return true;
         }
-        return None;
-    } else {
-        warn!(
-            "Unsupported refspec patterns, too many asterisks: {}",
-            pattern
-        );
+        matched.pop();
     }
-    None
+    false
 }
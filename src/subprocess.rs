@@ -1,42 +1,90 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::process::{Command, Stdio};
 
-use anyhow::{Context, Result};
-use git2::{BranchType, Config, Reference, Repository};
+use crate::error::{Error, Result};
+use crate::is_stdout_reserved;
+use git2::{BranchType, Config, Oid, Reference, Repository};
 use log::*;
 
 use crate::config::get_remote;
 use crate::remote_ref::get_fetch_remote_ref;
 
-fn git(repo: &Repository, args: &[&str]) -> Result<()> {
-    let workdir = repo.workdir().context("Bare repository is not supported")?;
-    let workdir = workdir.to_str().context("non utf-8 workdir")?;
-    info!("> git -C {} {}", workdir, args.join(" "));
-
-    let mut cd_args = vec!["-C", workdir];
-    cd_args.extend_from_slice(args);
-    let exit_status = Command::new("git").args(cd_args).status()?;
-    if !exit_status.success() {
-        Err(std::io::Error::from_raw_os_error(exit_status.code().unwrap_or(-1)).into())
+/// `-C <workdir>` for a repository with a working tree, `--git-dir <git dir>` for a bare repository.
+fn repo_args<'a>(repo: &'a Repository, args: &[&'a str]) -> Result<Vec<&'a str>> {
+    let mut repo_args = if let Some(workdir) = repo.workdir() {
+        vec![
+            "-C",
+            workdir.to_str().ok_or_else(|| Error::non_utf8("workdir"))?,
+        ]
     } else {
-        Ok(())
+        let git_dir = repo
+            .path()
+            .to_str()
+            .ok_or_else(|| Error::non_utf8("git dir"))?;
+        vec!["--git-dir", git_dir]
+    };
+    repo_args.extend_from_slice(args);
+    Ok(repo_args)
+}
+
+/// The stderr is passed through as it goes, so that the progress and the warnings are shown.
+/// The stdout goes to stderr while stdout is reserved.
+fn git(repo: &Repository, args: &[&str]) -> Result<()> {
+    run_git(repo, args, false)
+}
+
+/// Same as `git`, but the stderr is captured to be a part of the error for the report.
+/// It is passed through on success.
+fn git_capturing_stderr(repo: &Repository, args: &[&str]) -> Result<()> {
+    run_git(repo, args, true)
+}
+
+fn run_git(repo: &Repository, args: &[&str], capture_stderr: bool) -> Result<()> {
+    let args = repo_args(repo, args)?;
+    info!("> git {}", args.join(" "));
+
+    let pipe_or_inherit = |pipe| {
+        if pipe {
+            Stdio::piped()
+        } else {
+            Stdio::inherit()
+        }
+    };
+    let output = Command::new("git")
+        .args(&args)
+        .stdin(Stdio::inherit())
+        .stdout(pipe_or_inherit(is_stdout_reserved()))
+        .stderr(pipe_or_inherit(capture_stderr))
+        .output()?;
+    eprint!("{}", String::from_utf8_lossy(&output.stdout));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Err(Error::Subprocess {
+            command: format!("git {}", args.join(" ")),
+            status: output.status.to_string(),
+            stderr: stderr.trim().to_string(),
+        });
     }
+    eprint!("{}", stderr);
+    Ok(())
 }
 
 fn git_output(repo: &Repository, args: &[&str]) -> Result<String> {
-    let workdir = repo.workdir().context("Bare repository is not supported")?;
-    let workdir = workdir.to_str().context("non utf-8 workdir")?;
-    info!("> git -C {} {}", workdir, args.join(" "));
+    let args = repo_args(repo, args)?;
+    info!("> git {}", args.join(" "));
 
-    let mut cd_args = vec!["-C", workdir];
-    cd_args.extend_from_slice(args);
     let output = Command::new("git")
-        .args(cd_args)
+        .args(&args)
         .stdin(Stdio::null())
         .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
         .output()?;
     if !output.status.success() {
-        return Err(std::io::Error::from_raw_os_error(output.status.code().unwrap_or(-1)).into());
+        return Err(Error::Subprocess {
+            command: format!("git {}", args.join(" ")),
+            status: output.status.to_string(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
     }
 
     let str = std::str::from_utf8(&output.stdout)?.trim();
@@ -56,12 +104,16 @@ pub fn remote_update(repo: &Repository, dry_run: bool) -> Result<()> {
 }
 
 pub fn is_merged(repo: &Repository, base_remote_ref: &str, branch: &str) -> Result<bool> {
-    let merge_base = git_output(&repo, &["merge-base", base_remote_ref, branch])?;
+    let merge_base = get_merge_base(repo, base_remote_ref, branch)?;
     Ok(is_merged_by_rev_list(repo, base_remote_ref, branch)?
         || is_squash_merged(repo, &merge_base, base_remote_ref, branch)?)
 }
 
-fn is_merged_by_rev_list(repo: &Repository, base: &str, branch: &str) -> Result<bool> {
+pub fn get_merge_base(repo: &Repository, base: &str, branch: &str) -> Result<String> {
+    git_output(&repo, &["merge-base", base, branch])
+}
+
+pub fn is_merged_by_rev_list(repo: &Repository, base: &str, branch: &str) -> Result<bool> {
     let range = format!("{}...{}", base, branch);
     // Is there any revs that are not applied to the base in the branch?
     let output = git_output(
@@ -80,22 +132,87 @@ fn is_merged_by_rev_list(repo: &Repository, base: &str, branch: &str) -> Result<
     Ok(output.is_empty())
 }
 
-/// Source: https://stackoverflow.com/a/56026209
-fn is_squash_merged(repo: &Repository, merge_base: &str, base: &str, branch: &str) -> Result<bool> {
-    let tree = git_output(repo, &["rev-parse", &format!("{}^{{tree}}", branch)])?;
-    let dangling_commit = git_output(
+/// Is the cumulative patch of the branch applied to the base as a single commit?
+/// It is a variant of https://stackoverflow.com/a/56026209
+/// It compares patch ids instead of testing a squashed commit with `git commit-tree`,
+/// so it doesn't write dangling commits into the object database.
+pub fn is_squash_merged(
+    repo: &Repository,
+    merge_base: &str,
+    base: &str,
+    branch: &str,
+) -> Result<bool> {
+    let squashed = git_patch_ids(
         repo,
         &[
-            "commit-tree",
-            &tree,
-            "-p",
-            &merge_base,
-            "-m",
-            "git-trim: squash merge test",
+            "diff",
+            "--no-color",
+            "--no-ext-diff",
+            merge_base,
+            branch,
+            "--",
         ],
     )?;
+    if squashed.is_empty() {
+        return Ok(false);
+    }
 
-    is_merged_by_rev_list(repo, base, &dangling_commit)
+    let range = format!("{}..{}", merge_base, base);
+    let upstream = git_patch_ids(
+        repo,
+        &[
+            "log",
+            "--patch",
+            "--no-merges",
+            "--no-color",
+            "--no-ext-diff",
+            &range,
+            "--",
+        ],
+    )?;
+    Ok(squashed.is_subset(&upstream))
+}
+
+/// `git <args> | git patch-id --stable`
+fn git_patch_ids(repo: &Repository, args: &[&str]) -> Result<HashSet<String>> {
+    let args = repo_args(repo, args)?;
+    info!("> git {} | git patch-id --stable", args.join(" "));
+
+    let mut patch = Command::new("git")
+        .args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let patch_stdout = patch.stdout.take().expect("stdout is piped");
+    let output = Command::new("git")
+        .args(repo_args(repo, &["patch-id", "--stable"])?)
+        .stdin(patch_stdout)
+        .stdout(Stdio::piped())
+        .output()?;
+    let patch_status = patch.wait()?;
+    if !patch_status.success() {
+        return Err(Error::Subprocess {
+            command: format!("git {}", args.join(" ")),
+            status: patch_status.to_string(),
+            stderr: String::new(),
+        });
+    }
+    if !output.status.success() {
+        return Err(Error::Subprocess {
+            command: "git patch-id --stable".to_string(),
+            status: output.status.to_string(),
+            stderr: String::new(),
+        });
+    }
+
+    let mut result = HashSet::new();
+    for line in std::str::from_utf8(&output.stdout)?.lines() {
+        trace!("{}", line);
+        if let Some(patch_id) = line.split_whitespace().next() {
+            result.insert(patch_id.to_string());
+        }
+    }
+    Ok(result)
 }
 
 pub fn get_noff_merged_locals(
@@ -131,7 +248,10 @@ pub fn get_noff_merged_locals(
                 trace!("skip: it is symbolic");
                 continue;
             }
-            let branch_name = branch.name()?.context("no utf-8 branch name")?.to_string();
+            let branch_name = branch
+                .name()?
+                .ok_or_else(|| Error::non_utf8("branch name"))?
+                .to_string();
             trace!("noff merged local: it is merged to {}", base_remote_ref);
             result.insert(branch_name);
         }
@@ -139,18 +259,44 @@ pub fn get_noff_merged_locals(
     Ok(result)
 }
 
+/// Branches that are checked out in each worktree.
+/// refs/heads/<branch> -> <path of the worktree>
+pub fn get_worktree_heads(repo: &Repository) -> Result<HashMap<String, String>> {
+    let output = git_output(repo, &["worktree", "list", "--porcelain"])?;
+    let mut result = HashMap::new();
+    let mut worktree = None;
+    for line in output.lines() {
+        let mut attribute = line.splitn(2, ' ');
+        match (attribute.next(), attribute.next()) {
+            (Some("worktree"), Some(path)) => worktree = Some(path),
+            (Some("branch"), Some(refname)) => {
+                if let Some(worktree) = worktree {
+                    result.insert(refname.to_string(), worktree.to_string());
+                }
+            }
+            (Some(""), None) => worktree = None,
+            _ => {}
+        }
+    }
+    Ok(result)
+}
+
 pub fn checkout(repo: &Repository, head: Reference, dry_run: bool) -> Result<()> {
-    let head_refname = head.name().context("non-utf8 head ref name")?;
+    let head_refname = head
+        .name()
+        .ok_or_else(|| Error::non_utf8("head ref name"))?;
     if !dry_run {
         git(repo, &["checkout", head_refname])
     } else {
         info!("> git checkout {} (dry-run)", head_refname);
 
-        println!("Note: switching to '{}' (dry run)", head_refname);
-        println!("You are in 'detached HED' state... blah blah...");
+        message!("Note: switching to '{}' (dry run)", head_refname);
+        message!("You are in 'detached HED' state... blah blah...");
         let commit = head.peel_to_commit()?;
-        let message = commit.message().context("non-utf8 head ref name")?;
-        println!(
+        let message = commit
+            .message()
+            .ok_or_else(|| Error::non_utf8("head ref name"))?;
+        message!(
             "HEAD is now at {} {} (dry run)",
             &commit.id().to_string()[..7],
             message.lines().next().unwrap_or_default()
@@ -159,31 +305,172 @@ pub fn checkout(repo: &Repository, head: Reference, dry_run: bool) -> Result<()>
     }
 }
 
-pub fn branch_delete(repo: &Repository, branches: &[&str], dry_run: bool) -> Result<()> {
-    let mut args = vec!["branch", "--delete", "--force"];
-    args.extend(branches);
+pub fn branch_delete(repo: &Repository, branch: &str, dry_run: bool) -> Result<()> {
+    let args = ["branch", "--delete", "--force", branch];
+    if !dry_run {
+        git_capturing_stderr(repo, &args)
+    } else {
+        info!("> git {} (dry-run)", args.join(" "));
+        message!("Delete branch {} (dry run).", branch);
+        Ok(())
+    }
+}
+
+/// Delete the refs on the remote in a single push.
+/// Each ref is deleted only if it still points to the expected commit, like `--force-with-lease`,
+/// so commits that are pushed by others after the last fetch are never lost.
+/// A push isn't atomic, so each ref succeeds or fails on its own, and `--porcelain` tells which.
+/// The outer error is only for the failures before the push.
+pub fn push_delete(
+    repo: &Repository,
+    remote_name: &str,
+    expected: &[(String, Oid)],
+    dry_run: bool,
+) -> Result<Vec<(String, Result<()>)>> {
+    let leases: Vec<_> = expected
+        .iter()
+        .map(|(refname, oid)| format!("--force-with-lease={}:{}", refname, oid))
+        .collect();
+    let mut command = vec!["push", "--delete", "--porcelain"];
+    command.extend(leases.iter().map(String::as_str));
+    if dry_run {
+        command.push("--dry-run");
+    }
+    command.push(remote_name);
+    command.extend(expected.iter().map(|(refname, _)| refname.as_str()));
+    let args = repo_args(repo, &command)?;
+    info!("> git {}", args.join(" "));
+
+    let output = Command::new("git")
+        .args(&args)
+        .stdin(Stdio::inherit())
+        .output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    for line in stdout.lines() {
+        trace!("{}", line);
+    }
+
+    // <flag> \t <from>:<to> \t <summary>
+    let mut statuses = HashMap::new();
+    for line in stdout.lines() {
+        let mut fields = line.split('\t');
+        if let (Some(flag), Some(refs), Some(summary)) =
+            (fields.next(), fields.next(), fields.next())
+        {
+            let to = refs.rsplit(':').next().unwrap_or(refs);
+            statuses.insert(to.to_string(), (flag.to_string(), summary.to_string()));
+        }
+    }
+
+    let mut result = Vec::new();
+    for (refname, _) in expected {
+        let status = match statuses.get(refname) {
+            Some((flag, _)) if flag == "-" => Ok(()),
+            Some((_, summary)) if summary.contains("stale info") => {
+                Err(Error::ChangedOnRemote(refname.to_string()))
+            }
+            Some((_, summary)) => Err(Error::Subprocess {
+                command: format!("git {}", args.join(" ")),
+                status: summary.to_string(),
+                stderr: stderr.trim().to_string(),
+            }),
+            None => Err(Error::Subprocess {
+                command: format!("git {}", args.join(" ")),
+                status: output.status.to_string(),
+                stderr: stderr.trim().to_string(),
+            }),
+        };
+        result.push((refname.to_string(), status));
+    }
+    Ok(result)
+}
+
+/// Delete the remote tracking ref only in the local repository.
+pub fn update_ref_delete(repo: &Repository, remote_ref: &str, dry_run: bool) -> Result<()> {
+    let args = ["update-ref", "--no-deref", "-d", remote_ref];
+    if !dry_run {
+        git(repo, &args)
+    } else {
+        info!("> git {} (dry-run)", args.join(" "));
+        message!("Delete remote ref {} (dry run).", remote_ref);
+        Ok(())
+    }
+}
+
+/// The short form of an oid for the messages.
+/// The oid might be shorter than usual, e.g. from a journal that is edited by hand.
+pub(crate) fn abbrev(oid: &str) -> &str {
+    oid.get(..7).unwrap_or(oid)
+}
+
+/// Create a ref only if it doesn't exist.
+pub fn update_ref_create(repo: &Repository, refname: &str, oid: &str, dry_run: bool) -> Result<()> {
+    let args = ["update-ref", refname, oid, ""];
+    if !dry_run {
+        git(repo, &args)
+    } else {
+        info!("> git {} (dry-run)", args.join(" "));
+        message!("Create ref {} at {} (dry run).", refname, abbrev(oid));
+        Ok(())
+    }
+}
+
+pub fn tag_annotated(
+    repo: &Repository,
+    tag_name: &str,
+    oid: &str,
+    message: &str,
+    dry_run: bool,
+) -> Result<()> {
+    let args = ["tag", "--annotate", "--message", message, tag_name, oid];
     if !dry_run {
         git(repo, &args)
     } else {
-        for branch in branches {
-            info!("> git {} (dry-run)", args.join(" "));
-            println!("Delete branch {} (dry run).", branch);
+        info!("> git {} (dry-run)", args.join(" "));
+        message!("Create tag {} at {} (dry run).", tag_name, abbrev(oid));
+        Ok(())
+    }
+}
+
+/// Push the refs to the same refnames on the remote.
+pub fn push_refs(
+    repo: &Repository,
+    remote_name: &str,
+    refnames: &[&str],
+    dry_run: bool,
+) -> Result<()> {
+    let refspecs: Vec<_> = refnames
+        .iter()
+        .map(|refname| format!("{}:{}", refname, refname))
+        .collect();
+    let mut command = vec!["push", remote_name];
+    command.extend(refspecs.iter().map(String::as_str));
+    if !dry_run {
+        git(repo, &command)
+    } else {
+        // `git push --dry-run` would fail since the refs are not created in a dry run.
+        info!("> git {} (dry-run)", command.join(" "));
+        for refname in refnames {
+            message!("Push {} to {} (dry run).", refname, remote_name);
         }
         Ok(())
     }
 }
 
-pub fn push_delete(
+pub fn push_restore(
     repo: &Repository,
     remote_name: &str,
-    remote_refnames: &[String],
+    oid: &str,
+    refname: &str,
     dry_run: bool,
 ) -> Result<()> {
-    let mut command = vec!["push", "--delete"];
+    let refspec = format!("{}:{}", oid, refname);
+    let mut command = vec!["push"];
     if dry_run {
         command.push("--dry-run");
     }
     command.push(remote_name);
-    command.extend(remote_refnames.iter().map(String::as_str));
+    command.push(&refspec);
     git(repo, &command)
 }
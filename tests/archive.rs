@@ -0,0 +1,189 @@
+mod fixture;
+
+use std::convert::TryFrom;
+
+use anyhow::Result;
+use git2::{BranchType, ObjectType, Repository};
+
+use git_trim::archive::{Archive, ArchiveEntry, ArchiveKind};
+use git_trim::args::DeleteFilter;
+use git_trim::{
+    delete_local_branches, delete_remote_branches, get_merged_or_gone, subprocess, Config, Git,
+};
+
+use fixture::{rc, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin --bare
+
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+            git config remote.pushdefault origin
+            git config push.default simple
+
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+            git push -u origin master
+
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin feature
+
+            git checkout master
+            git merge feature
+            git push origin master
+        EOF
+        "#,
+    )
+}
+
+fn config() -> Config<'static> {
+    Config {
+        bases: vec!["master"],
+        protected_branches: set! {},
+        filter: DeleteFilter::all(),
+        detach: true,
+        stale_after: None,
+        forge: None,
+    }
+}
+
+fn archive(kind: ArchiveKind, remote: Option<&str>) -> Archive {
+    Archive {
+        kind,
+        date: "2020-01-01".to_string(),
+        remote: remote.map(str::to_string),
+    }
+}
+
+#[test]
+fn test_archive_refs() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(&git, &config())?;
+    let archive = archive(ArchiveKind::Ref, None);
+    let plan = archive.plan(&branches.to_delete);
+    assert_eq!(
+        plan,
+        vec![
+            ArchiveEntry {
+                refname: "refs/heads/feature".to_string(),
+                archive_refname: "refs/archive/2020-01-01/feature".to_string(),
+            },
+            ArchiveEntry {
+                refname: "refs/remotes/origin/feature".to_string(),
+                archive_refname: "refs/archive/2020-01-01/origin/feature".to_string(),
+            },
+        ]
+    );
+
+    let feature = git
+        .repo
+        .find_branch("feature", BranchType::Local)?
+        .get()
+        .target();
+    archive.archive(&git.repo, &plan, false)?;
+    // Archiving again is fine as long as the archives are the same.
+    archive.archive(&git.repo, &plan, false)?;
+    delete_remote_branches(&git.repo, &branches.to_delete.remotes(), false)?;
+    delete_local_branches(&git.repo, &branches.to_delete.locals(), false)?;
+
+    assert!(git.repo.find_branch("feature", BranchType::Local).is_err());
+    assert_eq!(
+        git.repo
+            .find_reference("refs/archive/2020-01-01/feature")?
+            .target(),
+        feature,
+    );
+    assert_eq!(
+        git.repo
+            .find_reference("refs/archive/2020-01-01/origin/feature")?
+            .target(),
+        feature,
+    );
+    Ok(())
+}
+
+#[test]
+fn test_archive_tags_to_remote() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(&git, &config())?;
+    let archive = archive(ArchiveKind::Tag, Some("origin"));
+    let plan = archive.plan(&branches.to_delete);
+
+    let feature = git
+        .repo
+        .find_branch("feature", BranchType::Local)?
+        .get()
+        .target();
+    archive.archive(&git.repo, &plan, false)?;
+    delete_remote_branches(&git.repo, &branches.to_delete.remotes(), false)?;
+    delete_local_branches(&git.repo, &branches.to_delete.locals(), false)?;
+
+    let origin = Repository::open(guard.working_directory().join("../origin"))?;
+    assert!(origin.find_reference("refs/heads/feature").is_err());
+    for repo in &[&git.repo, &origin] {
+        let tag = repo.find_reference("refs/tags/archive/2020-01-01/feature")?;
+        assert_eq!(tag.peel(ObjectType::Tag)?.kind(), Some(ObjectType::Tag));
+        assert_eq!(Some(tag.peel_to_commit()?.id()), feature);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_archive_conflict() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git update-ref refs/archive/2020-01-01/feature HEAD~1
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(&git, &config())?;
+    let archive = archive(ArchiveKind::Ref, None);
+    let plan = archive.plan(&branches.to_delete);
+    assert!(archive.archive(&git.repo, &plan, false).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_archive_to_remote_in_dry_run() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(&git, &config())?;
+    let archive = archive(ArchiveKind::Tag, Some("origin"));
+    let plan = archive.plan(&branches.to_delete);
+    archive.archive(&git.repo, &plan, true)?;
+
+    let origin = Repository::open(guard.working_directory().join("../origin"))?;
+    for repo in &[&git.repo, &origin] {
+        assert!(repo
+            .find_reference("refs/tags/archive/2020-01-01/feature")
+            .is_err());
+    }
+    Ok(())
+}
+
+#[test]
+fn test_short_oid_in_dry_run() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    let repo = Repository::open(guard.working_directory())?;
+    subprocess::update_ref_create(&repo, "refs/archive/2020-01-01/feature", "abc", true)?;
+    subprocess::tag_annotated(&repo, "archive/2020-01-01/feature", "abc", "Archive", true)?;
+    Ok(())
+}
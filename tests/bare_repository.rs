@@ -0,0 +1,109 @@
+mod fixture;
+
+use std::convert::TryFrom;
+
+use anyhow::Result;
+use git2::{BranchType, Repository};
+
+use git_trim::args::DeleteFilter;
+use git_trim::{delete_local_branches, get_merged_or_gone, Config, Git, MergedOrGone};
+
+use fixture::{rc, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin
+        origin <<EOF
+            git config user.name "Origin Test"
+            git config user.email "origin@test"
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git checkout master
+        EOF
+
+        git clone --bare origin local
+        local <<EOF
+            git config remote.origin.fetch '+refs/heads/*:refs/remotes/origin/*'
+            git fetch origin
+            git config branch.master.remote origin
+            git config branch.master.merge refs/heads/master
+            git config branch.feature.remote origin
+            git config branch.feature.merge refs/heads/feature
+        EOF
+        "#,
+    )
+}
+
+fn config() -> Config<'static> {
+    Config {
+        bases: vec!["master"],
+        protected_branches: set! {},
+        filter: DeleteFilter::all(),
+        detach: true,
+        stale_after: None,
+        forge: None,
+    }
+}
+
+#[test]
+fn test_merged_in_bare_repository() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git merge feature
+            git branch -d feature
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    assert!(git.repo.is_bare());
+    let branches = get_merged_or_gone(&git, &config())?;
+
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            merged_locals: set! {"feature"},
+            ..Default::default()
+        },
+    );
+
+    delete_local_branches(&git.repo, &branches.to_delete.locals(), false)?;
+    assert!(git.repo.find_branch("feature", BranchType::Local).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_head_of_bare_repository_is_kept_back() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git merge feature
+            git branch -d feature
+        EOF
+
+        local <<EOF
+            git symbolic-ref HEAD refs/heads/feature
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(&git, &config())?;
+
+    assert_eq!(branches.to_delete, MergedOrGone::default());
+    assert_eq!(
+        branches.kept_back.get("feature").map(String::as_str),
+        Some("Merged local but kept back because it is HEAD of the bare repository"),
+    );
+    Ok(())
+}
@@ -0,0 +1,104 @@
+mod fixture;
+
+use std::fs::{self, Permissions};
+use std::os::unix::fs::PermissionsExt;
+
+use anyhow::Result;
+
+use git_trim::batch::{discover_repositories, read_repository_list};
+
+use fixture::{rc, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin --bare
+
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+            git push -u origin master
+        EOF
+        "#,
+    )
+}
+
+#[test]
+fn test_discover_repositories() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        mkdir -p work/nested not-a-repo/empty
+        git clone origin work/nested/another
+        git clone --bare origin mirror.git
+        git init local/vendored
+        "#,
+    )?;
+    let root = guard.working_directory().join("..");
+
+    let repos = discover_repositories(&root)?;
+
+    assert_eq!(
+        repos,
+        vec![
+            root.join("local"),
+            root.join("mirror.git"),
+            root.join("work/nested/another"),
+        ],
+    );
+    Ok(())
+}
+
+#[test]
+fn test_discover_repositories_skips_unreadable_directories() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        mkdir -p locked/nested
+        git clone origin locked/nested/hidden
+        chmod 000 locked
+        "#,
+    )?;
+    let root = guard.working_directory().join("..");
+
+    let repos = discover_repositories(&root);
+    // The superuser can read it anyway.
+    let readable = fs::read_dir(root.join("locked")).is_ok();
+    // Let the guard clean it up.
+    fs::set_permissions(root.join("locked"), Permissions::from_mode(0o755))?;
+
+    let repos = repos?;
+    assert!(repos.contains(&root.join("local")));
+    assert_eq!(repos.contains(&root.join("locked/nested/hidden")), readable);
+    Ok(())
+}
+
+#[test]
+fn test_discover_repositories_fails_on_unreadable_root() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+    let root = guard.working_directory().join("../no-such-directory");
+
+    assert!(discover_repositories(&root).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_read_repository_list() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+    let root = guard.working_directory().join("..");
+    let list = root.join("repos.txt");
+    fs::write(
+        &list,
+        "# repositories to trim\n\nlocal\n  /absolute/path  \n",
+    )?;
+
+    let repos = read_repository_list(&list)?;
+
+    assert_eq!(repos, vec![root.join("local"), "/absolute/path".into()],);
+    Ok(())
+}
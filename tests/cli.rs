@@ -0,0 +1,258 @@
+mod fixture;
+
+use std::env;
+use std::path::Path;
+use std::process::{Command, Output};
+
+use anyhow::Result;
+
+use fixture::{rc, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin --bare
+
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+            git config remote.pushdefault origin
+            git config push.default simple
+
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+            git push -u origin master
+
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin feature
+
+            git checkout master
+            git merge feature --no-ff
+            git push -u origin master
+        EOF
+        "#,
+    )
+}
+
+/// Run the binary that is built next to the integration tests.
+fn git_trim(dir: &Path, args: &[&str]) -> Result<Output> {
+    let exe = env::current_exe()?;
+    let target_dir = exe
+        .parent()
+        .and_then(Path::parent)
+        .expect("tests are in target/<profile>/deps");
+    let output = Command::new(target_dir.join(format!("git-trim{}", env::consts::EXE_SUFFIX)))
+        .args(args)
+        .current_dir(dir)
+        .output()?;
+    println!("stdout: {}", String::from_utf8_lossy(&output.stdout));
+    println!("stderr: {}", String::from_utf8_lossy(&output.stderr));
+    Ok(output)
+}
+
+#[test]
+fn test_json_is_the_only_output_on_stdout() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    let output = git_trim(
+        &guard.working_directory(),
+        &["--format", "json", "--no-confirm"],
+    )?;
+
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert!(report["local_branches"].is_array());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Recorded to the journal"));
+    assert!(stderr.contains("Deleted branch feature"));
+    Ok(())
+}
+
+#[test]
+fn test_json_is_the_only_output_on_stdout_in_dry_run() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    let output = git_trim(
+        &guard.working_directory(),
+        &["--format", "json", "--dry-run"],
+    )?;
+
+    assert!(output.status.success());
+    let _: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Delete branch feature (dry run)."));
+    Ok(())
+}
+
+#[test]
+fn test_check_exits_with_3_if_there_are_branches_to_trim() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    let output = git_trim(&guard.working_directory(), &["--check"])?;
+
+    assert_eq!(output.status.code(), Some(3));
+    let output = Command::new("git")
+        .arg("branch")
+        .arg("--list")
+        .arg("feature")
+        .current_dir(guard.working_directory())
+        .output()?;
+    assert!(String::from_utf8_lossy(&output.stdout).contains("feature"));
+    Ok(())
+}
+
+#[test]
+fn test_check_exits_with_0_if_there_is_nothing_to_trim() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    let output = git_trim(&guard.working_directory(), &["--check", "--delete", "gone"])?;
+
+    assert_eq!(output.status.code(), Some(0));
+    Ok(())
+}
+
+#[test]
+fn test_exits_with_1_on_errors() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    // There is nothing to undo.
+    let output = git_trim(&guard.working_directory(), &["--undo"])?;
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr)?,
+        "Error: There is no journal to undo\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_exits_with_1_on_invalid_arguments() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    let output = git_trim(&guard.working_directory(), &["--delete", "foo"])?;
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = git_trim(&guard.working_directory(), &["--stale-after", "foo"])?;
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = git_trim(&guard.working_directory(), &["--update", "--no-update"])?;
+    assert_eq!(output.status.code(), Some(1));
+    Ok(())
+}
+
+#[test]
+fn test_exits_with_2_on_invalid_config() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config trim.staleAfter foo
+        EOF
+        "#,
+    )?;
+
+    let output = git_trim(&guard.working_directory(), &["--dry-run"])?;
+
+    assert_eq!(output.status.code(), Some(2));
+    Ok(())
+}
+
+#[test]
+fn test_exits_with_2_on_invalid_protected_pattern() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config trim.protected "regex:("
+        EOF
+        "#,
+    )?;
+
+    let output = git_trim(&guard.working_directory(), &["--dry-run"])?;
+    assert_eq!(output.status.code(), Some(2));
+
+    let output = git_trim(&guard.working_directory(), &["--check"])?;
+    assert_eq!(output.status.code(), Some(2));
+    Ok(())
+}
+
+#[test]
+fn test_exits_with_5_if_some_branches_are_not_deleted() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            cat > hooks/update <<'HOOK'
+        #!/bin/sh
+        echo "feature is locked" >&2
+        exit 1
+        HOOK
+            chmod +x hooks/update
+        EOF
+        "#,
+    )?;
+
+    let output = git_trim(&guard.working_directory(), &["--no-confirm"])?;
+
+    assert_eq!(output.status.code(), Some(5));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Deleted branch feature"));
+    assert!(stdout.contains("Failed to delete"));
+    Ok(())
+}
+
+#[test]
+fn test_json_of_a_repository_without_submodules_while_recursing() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config trim.recurseSubmodules true
+        EOF
+        "#,
+    )?;
+
+    let output = git_trim(
+        &guard.working_directory(),
+        &["--format", "json", "--dry-run"],
+    )?;
+
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert!(report["local_branches"].is_array());
+    Ok(())
+}
+
+#[test]
+fn test_json_of_a_repository_with_submodules_while_recursing() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config trim.recurseSubmodules true
+            git -c protocol.file.allow=always submodule add ../origin sub
+            git commit -m "Add a submodule"
+        EOF
+        "#,
+    )?;
+
+    let output = git_trim(
+        &guard.working_directory(),
+        &["--format", "json", "--dry-run"],
+    )?;
+
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    let repositories = report.as_object().expect("keyed by the paths");
+    assert_eq!(repositories.len(), 2);
+    for report in repositories.values() {
+        assert!(report["local_branches"].is_array());
+    }
+    Ok(())
+}
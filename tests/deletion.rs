@@ -0,0 +1,182 @@
+mod fixture;
+
+use std::convert::TryFrom;
+use std::process::Command;
+
+use anyhow::Result;
+use git2::{BranchType, Repository};
+
+use git_trim::args::DeleteFilter;
+use git_trim::{delete_local_branches, delete_remote_branches, get_merged_or_gone, Config, Git};
+
+use fixture::{rc, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin --bare
+
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+            git config remote.pushdefault origin
+            git config push.default simple
+
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+            git push -u origin master
+        EOF
+        "#,
+    )
+}
+
+fn config() -> Config<'static> {
+    Config {
+        bases: vec!["master"],
+        protected_branches: set! {},
+        filter: DeleteFilter::all(),
+        detach: true,
+        stale_after: None,
+        forge: None,
+    }
+}
+
+#[test]
+fn test_continue_on_error() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin feature
+
+            git checkout master
+            git checkout -b locked
+            touch locked-patch
+            git add locked-patch
+            git commit -m "Locked patch"
+            git push -u origin locked
+
+            git checkout master
+            git merge feature locked
+            git push origin master
+        EOF
+
+        origin <<EOF
+            cat > hooks/update <<'HOOK'
+        #!/bin/sh
+        if [ "\$1" = "refs/heads/locked" ]; then
+            echo "locked is locked" >&2
+            exit 1
+        fi
+        HOOK
+            chmod +x hooks/update
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(&git, &config())?;
+
+    let mut report = delete_remote_branches(&git.repo, &branches.to_delete.remotes(), false)?;
+    report.extend(delete_local_branches(
+        &git.repo,
+        &branches.to_delete.locals(),
+        false,
+    )?);
+
+    let mut deleted = report.deleted.clone();
+    deleted.sort();
+    assert_eq!(
+        deleted,
+        vec!["feature", "locked", "refs/remotes/origin/feature"]
+    );
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].refname, "refs/remotes/origin/locked");
+    assert!(
+        report.failed[0].message.contains("locked is locked"),
+        "{}",
+        report.failed[0].message
+    );
+
+    assert!(git.repo.find_branch("locked", BranchType::Local).is_err());
+    let origin = Repository::open(guard.working_directory().join("../origin"))?;
+    assert!(origin.find_reference("refs/heads/feature").is_err());
+    assert!(origin.find_reference("refs/heads/locked").is_ok());
+
+    let err = report.into_result().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Failed to delete 1 ref(s): refs/remotes/origin/locked"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_keep_changed_on_remote_since_fetch() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin feature
+
+            git checkout master
+            git merge feature
+            git push origin master
+        EOF
+
+        git clone origin colleague
+        within colleague <<EOF
+            git config user.name "Colleague Test"
+            git config user.email "colleague@test"
+
+            git checkout feature
+            touch another-patch
+            git add another-patch
+            git commit -m "Another patch"
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(&git, &config())?;
+    assert_eq!(
+        branches.to_delete.remotes(),
+        vec!["refs/remotes/origin/feature"]
+    );
+
+    // The colleague pushes after the classification.
+    let status = Command::new("git")
+        .arg("push")
+        .arg("origin")
+        .arg("feature")
+        .current_dir(guard.working_directory().join("../colleague"))
+        .status()?;
+    assert!(status.success());
+
+    let report = delete_remote_branches(&git.repo, &branches.to_delete.remotes(), false)?;
+
+    assert!(report.deleted.is_empty());
+    assert!(report.failed.is_empty());
+    assert_eq!(report.kept_back.len(), 1);
+    assert_eq!(report.kept_back[0].refname, "refs/remotes/origin/feature");
+    assert_eq!(report.kept_back[0].message, "changed on remote since fetch");
+
+    let origin = Repository::open(guard.working_directory().join("../origin"))?;
+    let colleague = Repository::open(guard.working_directory().join("../colleague"))?;
+    assert_eq!(
+        origin.refname_to_id("refs/heads/feature")?,
+        colleague.refname_to_id("refs/heads/feature")?
+    );
+    assert!(report.into_result().is_ok());
+    Ok(())
+}
@@ -0,0 +1,168 @@
+mod fixture;
+
+use std::convert::TryFrom;
+
+use anyhow::Result;
+use git2::Repository;
+
+use git_trim::args::DeleteFilter;
+use git_trim::{get_merged_or_gone, Config, Git, MergedOrGone};
+
+use fixture::{rc, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin --bare
+
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+            git config remote.pushdefault origin
+            git config push.default simple
+
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+            git push -u origin master
+
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin feature
+        EOF
+        "#,
+    )
+}
+
+fn config() -> Config<'static> {
+    Config {
+        bases: vec!["master"],
+        protected_branches: set! {},
+        filter: DeleteFilter::all(),
+        detach: true,
+        stale_after: None,
+        forge: None,
+    }
+}
+
+#[test]
+fn test_keep_diverged_remote() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        git clone origin colleague
+        within colleague <<EOF
+            git config user.name "Colleague Test"
+            git config user.email "colleague@test"
+
+            git checkout feature
+            touch another-patch
+            git add another-patch
+            git commit -m "Another patch"
+            git push origin feature
+        EOF
+
+        local <<EOF
+            git checkout master
+            git merge feature
+            git push origin master
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(&git, &config())?;
+
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            merged_locals: set! {"feature"},
+            ..Default::default()
+        },
+    );
+    assert_eq!(
+        branches
+            .kept_back
+            .get("refs/remotes/origin/feature")
+            .map(String::as_str),
+        Some("local merged, remote diverged: it has commits that are not merged"),
+    );
+    Ok(())
+}
+
+#[test]
+fn test_delete_remote_behind_local() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            touch another-patch
+            git add another-patch
+            git commit -m "Another patch"
+
+            git checkout master
+            git merge feature
+            git push origin master
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(&git, &config())?;
+
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            merged_locals: set! {"feature"},
+            merged_remotes: set! {"refs/remotes/origin/feature"},
+            ..Default::default()
+        },
+    );
+    assert!(branches.kept_back.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_delete_diverged_remote_that_is_merged() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        git clone origin colleague
+        within colleague <<EOF
+            git config user.name "Colleague Test"
+            git config user.email "colleague@test"
+
+            git checkout feature
+            touch another-patch
+            git add another-patch
+            git commit -m "Another patch"
+            git push origin feature
+
+            git checkout master
+            git merge feature
+            git push origin master
+        EOF
+
+        local <<EOF
+            git checkout master
+            git pull
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(&git, &config())?;
+
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            merged_locals: set! {"feature"},
+            merged_remotes: set! {"refs/remotes/origin/feature"},
+            ..Default::default()
+        },
+    );
+    Ok(())
+}
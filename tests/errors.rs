@@ -0,0 +1,91 @@
+mod fixture;
+
+use std::convert::TryFrom;
+
+use anyhow::Result;
+use git2::Repository;
+
+use git_trim::args::DeleteFilter;
+use git_trim::{get_merged_or_gone, Config, Error, Git};
+
+use fixture::{rc, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin --bare
+
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+            git config remote.pushdefault origin
+            git config push.default simple
+
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+            git push -u origin master
+
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin feature
+
+            git checkout master
+        EOF
+        "#,
+    )
+}
+
+fn config() -> Config<'static> {
+    Config {
+        bases: vec!["master"],
+        protected_branches: set! {},
+        filter: DeleteFilter::all(),
+        detach: true,
+        stale_after: None,
+        forge: None,
+    }
+}
+
+#[test]
+fn test_invalid_upstream_config() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config branch.feature.merge feature
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    match get_merged_or_gone(&git, &config()) {
+        Err(Error::Config { key, .. }) => assert_eq!(key, "branch.feature.merge"),
+        other => panic!("Unexpected result: {:?}", other.map(|_| ())),
+    }
+    Ok(())
+}
+
+#[test]
+fn test_unsupported_refspec() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    // git refuses to fetch with it, so it is set after the fixture is prepared.
+    let repo = Repository::open(guard.working_directory())?;
+    repo.config()?.set_str(
+        "remote.origin.fetch",
+        "refs/heads/*:refs/remotes/origin/main",
+    )?;
+
+    let git = Git::try_from(repo)?;
+    match get_merged_or_gone(&git, &config()) {
+        Err(Error::UnsupportedRefspec(refspec)) => {
+            assert_eq!(refspec, "refs/heads/*:refs/remotes/origin/main")
+        }
+        other => panic!("Unexpected result: {:?}", other.map(|_| ())),
+    }
+    Ok(())
+}
@@ -0,0 +1,141 @@
+mod fixture;
+
+use std::convert::TryFrom;
+
+use anyhow::Result;
+use git2::Repository;
+
+use git_trim::args::DeleteFilter;
+use git_trim::explain::explain;
+use git_trim::{Config, Git};
+
+use fixture::{rc, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin --bare
+
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+            git config remote.pushdefault origin
+            git config push.default simple
+
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+            git push -u origin master
+        EOF
+        "#,
+    )
+}
+
+fn config() -> Config<'static> {
+    Config {
+        bases: vec!["master"],
+        protected_branches: set! {},
+        filter: DeleteFilter::merged(),
+        detach: true,
+        stale_after: None,
+        forge: None,
+    }
+}
+
+#[test]
+fn test_explain_merged() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin feature
+
+            git checkout master
+            git merge --squash feature
+            git commit -m "Squashed"
+            git push origin master
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let explanation = explain(&git, &config(), "feature")?;
+    println!("{}", explanation);
+
+    assert_eq!(
+        explanation,
+        r#"Branch feature (refs/heads/feature)
+Upstream:
+  remote: origin (from branch.feature.remote)
+  merge: refs/heads/feature (from branch.feature.merge)
+  fetch upstream: refs/remotes/origin/feature
+Push:
+  push remote: origin (from remote.pushDefault)
+  push.default: simple (from push.default)
+  push destination: refs/heads/feature on origin
+  push upstream: none
+Merge tests:
+  against refs/remotes/origin/master:
+    git branch --merged: not merged
+    patches: merged by cherry-pick
+    classified: merged local, merged remote: the branch is merged, but forgot to delete
+Protection:
+  base: no
+  marked: no
+Filter:
+  merged local feature: selected
+  merged remote refs/remotes/origin/feature: selected
+Decision:
+  feature: delete
+  refs/remotes/origin/feature: delete on the remote
+"#
+    );
+    Ok(())
+}
+
+#[test]
+fn test_explain_protected() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b release-v1
+            touch release
+            git add release
+            git commit -m "Release"
+            git push -u origin release-v1
+
+            git checkout master
+            git merge release-v1
+            git push origin master
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let explanation = explain(
+        &git,
+        &Config {
+            protected_branches: set! {"release-*"},
+            ..config()
+        },
+        "release-v1",
+    )?;
+    println!("{}", explanation);
+
+    assert!(explanation.contains(
+        "  pattern 'release-*': protects release-v1, refs/heads/release-v1, refs/remotes/origin/release-v1\n"
+    ));
+    assert!(explanation.contains("    git branch --merged: merged\n"));
+    assert!(explanation.contains("    classified: skip: the branch is protected\n"));
+    assert!(explanation.contains("Filter:\n  nothing to filter\n"));
+    assert!(explanation
+        .contains("  release-v1: keep: Merged local but kept back because it is protected\n"));
+    assert!(explanation.contains("  refs/remotes/origin/release-v1: keep\n"));
+    Ok(())
+}
@@ -65,6 +65,8 @@ fn config() -> Config<'static> {
             FilterUnit::MergedRemote(Scope::Scoped("origin".to_string())),
         ]),
         detach: true,
+        stale_after: None,
+        forge: None,
     }
 }
 
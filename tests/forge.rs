@@ -0,0 +1,425 @@
+mod fixture;
+
+use std::convert::TryFrom;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use anyhow::Result;
+use git2::Repository;
+
+use git_trim::args::DeleteFilter;
+use git_trim::forge::{GitHub, Gitea};
+use git_trim::{get_merged_or_gone, Config, Git, MergedOrGone};
+
+use fixture::{rc, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin --bare
+
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+            git config remote.pushdefault origin
+            git config push.default simple
+
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+            git push -u origin master
+        EOF
+        "#,
+    )
+}
+
+fn config() -> Config<'static> {
+    Config {
+        bases: vec!["master"],
+        protected_branches: set! {},
+        filter: DeleteFilter::all(),
+        detach: true,
+        stale_after: None,
+        forge: None,
+    }
+}
+
+/// A stand-in for the forge API. It answers each request with the headers and the body
+/// from `respond`, and records the request lines with the authorization headers.
+fn serve<F>(respond: F) -> Result<(String, Arc<Mutex<Vec<String>>>)>
+where
+    F: Fn(&str, &str) -> (String, String) + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let api_url = format!("http://{}", listener.local_addr()?);
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let recorded = requests.clone();
+    let base_url = api_url.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = String::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if request.is_empty() || line.starts_with("Authorization") {
+                    request.push_str(line.trim());
+                    request.push(' ');
+                }
+            }
+            let (headers, body) = respond(&base_url, &request);
+            recorded.lock().unwrap().push(request);
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+                body.len(),
+                headers,
+                body
+            )
+            .unwrap();
+        }
+    });
+    Ok((api_url, requests))
+}
+
+/// GitHub answers the pull requests of the head branch.
+fn serve_github(pulls: Vec<(&'static str, String)>) -> Result<(String, Arc<Mutex<Vec<String>>>)> {
+    serve(move |_, request| {
+        // GET /repos/<owner>/<repo>/pulls?state=all&head=<owner>%3A<branch> HTTP/1.1
+        let body = pulls
+            .iter()
+            .find(|(branch, _)| request.contains(&format!("%3A{} ", branch)))
+            .map(|(_, body)| body.clone())
+            .unwrap_or_else(|| "[]".to_string());
+        (String::new(), body)
+    })
+}
+
+fn sha(git: &Git, refname: &str) -> Result<String> {
+    Ok(git.repo.refname_to_id(refname)?.to_string())
+}
+
+#[test]
+fn test_pull_request_states() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b squashed
+            touch squashed-patch
+            git add squashed-patch
+            git commit -m "Squashed patch"
+            git push -u origin squashed
+
+            git checkout master
+            git checkout -b rejected
+            touch rejected-patch
+            git add rejected-patch
+            git commit -m "Rejected patch"
+            git push -u origin rejected
+
+            git checkout master
+            git checkout -b draft
+            git push -u origin draft
+
+            git checkout master
+            echo "Edited while squashing" > squashed-patch
+            git add squashed-patch
+            git commit -m "Squashed patch with edits"
+            git push origin master
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let (api_url, requests) = serve_github(vec![
+        (
+            "squashed",
+            format!(
+                r#"[{{"state": "closed", "merged_at": "2020-01-01T00:00:00Z", "head": {{"sha": "{}"}}}}]"#,
+                sha(&git, "refs/remotes/origin/squashed")?
+            ),
+        ),
+        (
+            "rejected",
+            format!(
+                r#"[{{"state": "closed", "merged_at": null, "head": {{"sha": "{}"}}}}]"#,
+                sha(&git, "refs/remotes/origin/rejected")?
+            ),
+        ),
+        (
+            "draft",
+            format!(
+                r#"[{{"state": "open", "merged_at": null, "head": {{"sha": "{}"}}}}]"#,
+                sha(&git, "refs/remotes/origin/draft")?
+            ),
+        ),
+    ])?;
+    let forge = GitHub {
+        api_url,
+        token: Some("secret".to_string()),
+    };
+
+    let branches = get_merged_or_gone(
+        &git,
+        &Config {
+            forge: Some(&forge),
+            ..config()
+        },
+    )?;
+
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            merged_locals: set! {"squashed"},
+            merged_remotes: set! {"refs/remotes/origin/squashed"},
+            gone_locals: set! {"rejected"},
+            gone_remotes: set! {"refs/remotes/origin/rejected"},
+            ..Default::default()
+        },
+    );
+    assert_eq!(
+        branches.kept_back.get("draft").map(String::as_str),
+        Some("Merged local but kept back because its pull request is open"),
+    );
+    let requests = requests.lock().unwrap();
+    assert!(requests
+        .iter()
+        .all(|request| request.contains("Authorization: token secret")));
+    Ok(())
+}
+
+#[test]
+fn test_pull_request_state_with_push_default_current() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config push.default current
+            git checkout -b rejected
+            touch rejected-patch
+            git add rejected-patch
+            git commit -m "Rejected patch"
+            git push -u origin rejected
+            git checkout master
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let (api_url, requests) = serve_github(vec![(
+        "rejected",
+        format!(
+            r#"[{{"state": "closed", "merged_at": null, "head": {{"sha": "{}"}}}}]"#,
+            sha(&git, "refs/remotes/origin/rejected")?
+        ),
+    )])?;
+    let forge = GitHub {
+        api_url,
+        token: None,
+    };
+
+    let branches = get_merged_or_gone(
+        &git,
+        &Config {
+            forge: Some(&forge),
+            ..config()
+        },
+    )?;
+
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            gone_locals: set! {"rejected"},
+            gone_remotes: set! {"refs/remotes/origin/rejected"},
+            ..Default::default()
+        },
+    );
+    assert!(!requests.lock().unwrap().is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_git_history_without_forge() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b draft
+            git push -u origin draft
+            git checkout master
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(&git, &config())?;
+
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            merged_locals: set! {"draft"},
+            merged_remotes: set! {"refs/remotes/origin/draft"},
+            ..Default::default()
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_ignore_pull_request_of_reused_branch_name() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin feature
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    // The pull request of the old `feature` branch that is merged before.
+    let (api_url, _) = serve_github(vec![(
+        "feature",
+        r#"[{"state": "closed", "merged_at": "2020-01-01T00:00:00Z", "head": {"sha": "0123456789abcdef0123456789abcdef01234567"}}]"#.to_string(),
+    )])?;
+    let forge = GitHub {
+        api_url,
+        token: None,
+    };
+    let branches = get_merged_or_gone(
+        &git,
+        &Config {
+            forge: Some(&forge),
+            ..config()
+        },
+    )?;
+
+    assert_eq!(branches.to_delete, MergedOrGone::default());
+    Ok(())
+}
+
+#[test]
+fn test_gitea_pages() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b rejected
+            touch rejected-patch
+            git add rejected-patch
+            git commit -m "Rejected patch"
+            git push -u origin rejected
+
+            git checkout master
+            git checkout -b another
+            touch another-patch
+            git add another-patch
+            git commit -m "Another patch"
+            git push -u origin another
+            git checkout master
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let second_page = format!(
+        r#"[{{"state": "closed", "merged": false, "head": {{"ref": "rejected", "sha": "{}"}}}}]"#,
+        sha(&git, "refs/remotes/origin/rejected")?
+    );
+    let (api_url, requests) = serve(move |api_url, request| {
+        if request.contains("page=2") {
+            (String::new(), second_page.clone())
+        } else {
+            let link = format!(
+                "Link: <{}/repos/origin/pulls?state=all&limit=50&page=2>; rel=\"next\"\r\n",
+                api_url
+            );
+            let first_page = r#"[{"state": "open", "head": {"ref": "other", "sha": "0"}}]"#;
+            (link, first_page.to_string())
+        }
+    })?;
+    let forge = Gitea::new(api_url, None);
+    let branches = get_merged_or_gone(
+        &git,
+        &Config {
+            forge: Some(&forge),
+            ..config()
+        },
+    )?;
+
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            gone_locals: set! {"rejected"},
+            gone_remotes: set! {"refs/remotes/origin/rejected"},
+            ..Default::default()
+        },
+    );
+    // The pages are read once for both branches.
+    assert_eq!(requests.lock().unwrap().len(), 2);
+    Ok(())
+}
+
+#[test]
+fn test_ask_once_for_bases_on_the_same_remote() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b develop
+            git push -u origin develop
+
+            git checkout -b rejected
+            touch rejected-patch
+            git add rejected-patch
+            git commit -m "Rejected patch"
+            git push -u origin rejected
+            git checkout master
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let (api_url, requests) = serve_github(vec![(
+        "rejected",
+        format!(
+            r#"[{{"state": "closed", "merged_at": null, "head": {{"sha": "{}"}}}}]"#,
+            sha(&git, "refs/remotes/origin/rejected")?
+        ),
+    )])?;
+    let forge = GitHub {
+        api_url,
+        token: None,
+    };
+    let branches = get_merged_or_gone(
+        &git,
+        &Config {
+            bases: vec!["develop", "master"],
+            forge: Some(&forge),
+            ..config()
+        },
+    )?;
+
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            gone_locals: set! {"rejected"},
+            gone_remotes: set! {"refs/remotes/origin/rejected"},
+            ..Default::default()
+        },
+    );
+    assert_eq!(requests.lock().unwrap().len(), 1);
+    Ok(())
+}
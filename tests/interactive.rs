@@ -0,0 +1,117 @@
+mod fixture;
+
+use std::convert::TryFrom;
+
+use anyhow::Result;
+use git2::Repository;
+
+use git_trim::args::DeleteFilter;
+use git_trim::interactive::{candidates, retain_selected};
+use git_trim::{get_merged_or_gone, Config, Git, MergedOrGone};
+
+use fixture::{rc, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin --bare
+        git init fork --bare
+
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+            git config remote.pushdefault origin
+            git config push.default simple
+            git remote add fork ../fork
+
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+            git push -u origin master
+
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin feature
+
+            git checkout master
+            git checkout -b forked
+            touch forked-patch
+            git add forked-patch
+            git commit -m "Forked patch"
+            git push -u fork forked
+
+            git checkout master
+            git merge feature forked
+            git push origin master
+
+            git update-ref refs/remotes/removed/old HEAD
+        EOF
+        "#,
+    )
+}
+
+fn config() -> Config<'static> {
+    Config {
+        bases: vec!["master"],
+        protected_branches: set! {},
+        filter: DeleteFilter::all(),
+        detach: true,
+        stale_after: None,
+        forge: None,
+    }
+}
+
+#[test]
+fn test_deselected_ones_are_kept() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(
+        &git,
+        &Config {
+            filter: "merged-local,merged-remote:origin,merged-remote-local:fork,orphaned"
+                .parse()?,
+            ..config()
+        },
+    )?;
+    let everything = MergedOrGone {
+        merged_locals: set! {"feature", "forked"},
+        merged_remotes: set! {"refs/remotes/origin/feature", "refs/remotes/fork/forked"},
+        orphaned_remotes: set! {"refs/remotes/removed/old"},
+        local_only: set! {"refs/remotes/fork/forked"},
+        ..Default::default()
+    };
+    assert_eq!(branches.to_delete, everything);
+
+    let report = branches.report(&git.repo)?;
+    let candidates = candidates(&report);
+    let all: Vec<_> = (0..candidates.len()).collect();
+    assert_eq!(
+        retain_selected(&branches.to_delete, &candidates, &all),
+        everything
+    );
+
+    for deselected in &[
+        "refs/heads/feature",
+        "refs/remotes/origin/feature",
+        "refs/remotes/fork/forked",
+        "refs/remotes/removed/old",
+    ] {
+        let selected: Vec<_> = (0..candidates.len())
+            .filter(|index| candidates[*index].refname != *deselected)
+            .collect();
+        assert_eq!(selected.len(), candidates.len() - 1);
+
+        let result = retain_selected(&branches.to_delete, &candidates, &selected);
+
+        let mut expected = everything.clone();
+        let deselected = deselected.to_string();
+        expected.retain(|x| *x != deselected && format!("refs/heads/{}", x) != deselected);
+        assert_eq!(result, expected, "deselected {}", deselected);
+        assert_ne!(result, everything);
+    }
+    Ok(())
+}
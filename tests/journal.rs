@@ -0,0 +1,216 @@
+mod fixture;
+
+use std::convert::TryFrom;
+use std::process::Command;
+
+use anyhow::Result;
+use git2::{BranchType, Repository};
+
+use git_trim::args::DeleteFilter;
+use git_trim::journal::{Journal, LocalRemoteRefEntry};
+use git_trim::{
+    delete_local_branches, delete_remote_branches, get_merged_or_gone, Config, Git, MergedOrGone,
+};
+
+use fixture::{rc, Fixture, FixtureGuard};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin --bare
+
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+            git config remote.pushdefault origin
+            git config push.default simple
+
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+            git push -u origin master
+        EOF
+        "#,
+    )
+}
+
+fn config() -> Config<'static> {
+    Config {
+        bases: vec!["master"],
+        protected_branches: set! {},
+        filter: DeleteFilter::all(),
+        detach: true,
+        stale_after: None,
+        forge: None,
+    }
+}
+
+#[test]
+fn test_undo() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin feature
+
+            git checkout master
+            git merge feature
+            git push origin master
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(&git, &config())?;
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            merged_locals: set! {"feature"},
+            merged_remotes: set! {"refs/remotes/origin/feature"},
+            ..Default::default()
+        },
+    );
+
+    let locals = branches.to_delete.locals();
+    let remotes = branches.to_delete.remotes();
+    let journal = Journal::record(&git.repo, &git.config, &locals, &remotes, &[])?;
+    journal.save(&git.repo)?;
+    delete_remote_branches(&git.repo, &remotes, false)?;
+    delete_local_branches(&git.repo, &locals, false)?;
+    assert!(git.repo.find_branch("feature", BranchType::Local).is_err());
+
+    let journal = Journal::load(&git.repo, None)?;
+    journal.undo(&git.repo, true, false)?;
+
+    let feature = git.repo.find_branch("feature", BranchType::Local)?;
+    let config = git.repo.config()?.snapshot()?;
+    assert_eq!(config.get_str("branch.feature.remote")?, "origin");
+    assert_eq!(
+        config.get_str("branch.feature.merge")?,
+        "refs/heads/feature"
+    );
+    let origin = Repository::open(guard.working_directory().join("../origin"))?;
+    assert_eq!(
+        origin.find_reference("refs/heads/feature")?.target(),
+        feature.get().target(),
+    );
+    Ok(())
+}
+
+fn merged_feature_and_colleague() -> &'static str {
+    r#"
+    local <<EOF
+        git checkout -b feature
+        touch awesome-patch
+        git add awesome-patch
+        git commit -m "Awesome patch"
+        git push -u origin feature
+
+        git checkout master
+        git merge feature
+        git push origin master
+    EOF
+
+    git clone origin colleague
+    within colleague <<EOF
+        git config user.name "Colleague Test"
+        git config user.email "colleague@test"
+    EOF
+    "#
+}
+
+fn colleague(guard: &FixtureGuard, script: &str) -> Result<()> {
+    let status = Command::new("bash")
+        .arg("-ec")
+        .arg(script)
+        .current_dir(guard.working_directory().join("../colleague"))
+        .status()?;
+    assert!(status.success());
+    Ok(())
+}
+
+#[test]
+fn test_journal_leaves_out_kept_back() -> Result<()> {
+    let guard = fixture().prepare("local", merged_feature_and_colleague())?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(&git, &config())?;
+    let locals = branches.to_delete.locals();
+    let remotes = branches.to_delete.remotes();
+    let mut journal = Journal::record(&git.repo, &git.config, &locals, &remotes, &[])?;
+    assert_eq!(journal.remote_refs.len(), 1);
+
+    // The colleague pushes after the classification, so the remote branch is kept back.
+    colleague(
+        &guard,
+        "git checkout feature && git commit --allow-empty -m 'Another patch' && git push origin feature",
+    )?;
+
+    let mut report = delete_remote_branches(&git.repo, &remotes, false)?;
+    report.extend(delete_local_branches(&git.repo, &locals, false)?);
+    assert_eq!(report.kept_back.len(), 1);
+
+    journal.retain_deleted(&report);
+    assert_eq!(journal.local_branches.len(), 1);
+    assert_eq!(journal.local_branches[0].name, "feature");
+    assert!(journal.remote_refs.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_undo_carries_on_after_failure() -> Result<()> {
+    let guard = fixture().prepare("local", merged_feature_and_colleague())?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(&git, &config())?;
+    let locals = branches.to_delete.locals();
+    let remotes = branches.to_delete.remotes();
+    let mut journal = Journal::record(&git.repo, &git.config, &locals, &remotes, &[])?;
+    journal.local_remote_refs.push(LocalRemoteRefEntry {
+        remote_ref: "refs/remotes/origin/restored-after-failure".to_string(),
+        tip: journal.local_branches[0].tip.clone(),
+    });
+    delete_remote_branches(&git.repo, &remotes, false)?.into_result()?;
+    delete_local_branches(&git.repo, &locals, false)?.into_result()?;
+
+    // Someone else reuses the name on the remote, so pushing it back is rejected.
+    colleague(
+        &guard,
+        "git checkout -b feature && git commit --allow-empty -m 'Unrelated' && git push origin feature",
+    )?;
+
+    assert!(journal.undo(&git.repo, true, false).is_err());
+    assert!(git.repo.find_branch("feature", BranchType::Local).is_ok());
+    assert!(git
+        .repo
+        .find_reference("refs/remotes/origin/feature")
+        .is_err());
+    assert!(git
+        .repo
+        .find_reference("refs/remotes/origin/restored-after-failure")
+        .is_ok());
+    Ok(())
+}
+
+#[test]
+fn test_undo_hand_edited_short_tip() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let journal = Journal {
+        run_id: "0".to_string(),
+        local_branches: vec![],
+        remote_refs: vec![],
+        local_remote_refs: vec![LocalRemoteRefEntry {
+            remote_ref: "refs/remotes/origin/feature".to_string(),
+            tip: "abc".to_string(),
+        }],
+    };
+    journal.undo(&git.repo, false, true)?;
+    Ok(())
+}
@@ -5,7 +5,7 @@ use std::convert::TryFrom;
 use anyhow::Result;
 use git2::Repository;
 
-use git_trim::{get_merged_or_gone, Config, Git, MergedOrGone};
+use git_trim::{get_merged_or_gone, is_merged, native, subprocess, Config, Git, MergedOrGone};
 
 use fixture::{rc, Fixture};
 use git_trim::args::DeleteFilter;
@@ -49,6 +49,8 @@ fn config() -> Config<'static> {
         protected_branches: set! {},
         filter: DeleteFilter::all(),
         detach: true,
+        stale_after: None,
+        forge: None,
     }
 }
 
@@ -129,6 +131,42 @@ fn test_squash() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_squash_does_not_write_objects() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature --squash && git commit --no-edit
+            git branch -D feature
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let count_objects = || -> Result<usize> {
+        let mut count = 0;
+        git.repo.odb()?.foreach(|_| {
+            count += 1;
+            true
+        })?;
+        Ok(count)
+    };
+
+    let base = "refs/remotes/origin/master";
+    let before = count_objects()?;
+    let merge_base = subprocess::get_merge_base(&git.repo, base, "feature")?;
+    assert!(subprocess::is_squash_merged(
+        &git.repo,
+        &merge_base,
+        base,
+        "feature"
+    )?);
+    assert_eq!(count_objects()?, before);
+    Ok(())
+}
+
 #[test]
 fn test_mixed() -> Result<()> {
     let fixture = rc().append_fixture_trace(
@@ -203,3 +241,70 @@ fn test_mixed() -> Result<()> {
     );
     Ok(())
 }
+
+fn assert_merged(git: &Git, branch: &str, expected: bool) -> Result<()> {
+    let base = "refs/remotes/origin/master";
+    assert_eq!(native::is_merged(&git.repo, base, branch)?, expected);
+    assert_eq!(subprocess::is_merged(&git.repo, base, branch)?, expected);
+    assert_eq!(is_merged(&git.repo, base, branch)?, expected);
+    Ok(())
+}
+
+#[test]
+fn test_native_and_subprocess_agree() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git checkout master
+            git merge feature --squash && git commit --no-edit
+        EOF
+        local <<EOF
+            git checkout -b unmerged master
+            touch unmerged-patch
+            git add unmerged-patch
+            git commit -m "Unmerged patch"
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    assert_merged(&git, "feature", true)?;
+    assert_merged(&git, "unmerged", false)?;
+    Ok(())
+}
+
+#[test]
+fn test_fallback_to_subprocess() -> Result<()> {
+    // The blob of a later commit of the base is lost.
+    // The patches of the base can't be computed in-process, while git can tell
+    // that the branch is merged from the ancestry alone.
+    let guard = rc().prepare(
+        "local",
+        r#"
+        git init local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+            git commit --allow-empty -m "Initial commit"
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git checkout master
+            git merge feature --ff-only
+            echo "Lost" > lost-patch
+            git add lost-patch
+            git commit -m "Lost patch"
+            rm .git/objects/\$(git rev-parse HEAD:lost-patch | sed 's|^..|&/|')
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let base = "refs/heads/master";
+    let branch = "refs/heads/feature";
+    assert!(native::is_merged(&git.repo, base, branch).is_err());
+    assert!(is_merged(&git.repo, base, branch)?);
+    Ok(())
+}
@@ -0,0 +1,137 @@
+mod fixture;
+
+use std::convert::TryFrom;
+
+use anyhow::Result;
+use git2::{BranchType, Repository};
+
+use git_trim::args::DeleteFilter;
+use git_trim::{delete_remote_refs_locally, get_merged_or_gone, Config, Git, MergedOrGone};
+
+use fixture::{rc, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin --bare
+
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+            git config remote.pushdefault origin
+            git config push.default simple
+
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+            git push -u origin master
+        EOF
+
+        git clone origin contributer
+        within contributer <<EOF
+            git config user.name "Contributer Test"
+            git config user.email "contributer@test"
+
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+        EOF
+
+        local <<EOF
+            git remote add contributer ../contributer
+            git remote update
+        EOF
+        "#,
+    )
+}
+
+fn config() -> Config<'static> {
+    Config {
+        bases: vec!["master"],
+        protected_branches: set! {},
+        filter: DeleteFilter::all(),
+        detach: true,
+        stale_after: None,
+        forge: None,
+    }
+}
+
+#[test]
+fn test_orphaned_remote_refs_of_removed_remote() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config --remove-section remote.contributer
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(&git, &config())?;
+
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            orphaned_remotes: set! {
+                "refs/remotes/contributer/feature",
+                "refs/remotes/contributer/master",
+            },
+            ..Default::default()
+        },
+    );
+
+    delete_remote_refs_locally(&git.repo, &branches.to_delete.orphans(), false).into_result()?;
+    assert!(git
+        .repo
+        .find_branch("contributer/feature", BranchType::Remote)
+        .is_err());
+    let contributer = Repository::open(guard.working_directory().join("../contributer"))?;
+    assert!(contributer
+        .find_branch("feature", BranchType::Local)
+        .is_ok());
+    Ok(())
+}
+
+#[test]
+fn test_orphaned_remote_refs_are_filtered() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config remote.contributer.fetch '+refs/heads/*:refs/remotes/contrib/*'
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(
+        &git,
+        &Config {
+            filter: DeleteFilter::merged(),
+            ..config()
+        },
+    )?;
+    assert_eq!(branches.to_delete, MergedOrGone::default());
+
+    let branches = get_merged_or_gone(
+        &git,
+        &Config {
+            filter: "orphaned".parse()?,
+            ..config()
+        },
+    )?;
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            orphaned_remotes: set! {
+                "refs/remotes/contributer/feature",
+                "refs/remotes/contributer/master",
+            },
+            ..Default::default()
+        },
+    );
+    Ok(())
+}
@@ -0,0 +1,185 @@
+mod fixture;
+
+use std::convert::TryFrom;
+
+use anyhow::Result;
+use git2::Repository;
+
+use git_trim::args::DeleteFilter;
+use git_trim::{get_merged_or_gone, Config, Git, MergedOrGone};
+
+use fixture::{rc, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin --bare
+
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+            git config remote.pushdefault origin
+            git config push.default simple
+
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+            git push -u origin master
+
+            git checkout -b release-v1
+            touch release-v1
+            git add release-v1
+            git commit -m "Release v1"
+            git push -u origin release-v1
+
+            git checkout master
+            git checkout -b release-tmp-1
+            touch release-tmp-1
+            git add release-tmp-1
+            git commit -m "Temporary release"
+            git push -u origin release-tmp-1
+
+            git checkout master
+            git merge release-v1 release-tmp-1
+            git push origin master
+        EOF
+        "#,
+    )
+}
+
+fn config() -> Config<'static> {
+    Config {
+        bases: vec!["master"],
+        protected_branches: set! {},
+        filter: DeleteFilter::all(),
+        detach: true,
+        stale_after: None,
+        forge: None,
+    }
+}
+
+#[test]
+fn test_negated_pattern() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(
+        &git,
+        &Config {
+            protected_branches: set! {"!release-tmp-*", "release-*"},
+            ..config()
+        },
+    )?;
+
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            merged_locals: set! {"release-tmp-1"},
+            merged_remotes: set! {"refs/remotes/origin/release-tmp-1"},
+            ..Default::default()
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_regex_pattern() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(
+        &git,
+        &Config {
+            protected_branches: set! {"regex:release-v[0-9]+"},
+            ..config()
+        },
+    )?;
+
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            merged_locals: set! {"release-tmp-1"},
+            merged_remotes: set! {"refs/remotes/origin/release-tmp-1"},
+            ..Default::default()
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_regex_pattern_matches_whole_name() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(
+        &git,
+        &Config {
+            protected_branches: set! {"regex:release"},
+            ..config()
+        },
+    )?;
+
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            merged_locals: set! {"release-v1", "release-tmp-1"},
+            merged_remotes: set! {
+                "refs/remotes/origin/release-v1",
+                "refs/remotes/origin/release-tmp-1"
+            },
+            ..Default::default()
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_remote_pattern() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(
+        &git,
+        &Config {
+            protected_branches: set! {"remote:origin/release-*"},
+            ..config()
+        },
+    )?;
+
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            merged_locals: set! {"release-v1", "release-tmp-1"},
+            ..Default::default()
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_remote_pattern_of_other_remote() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(
+        &git,
+        &Config {
+            protected_branches: set! {"remote:upstream/release-*"},
+            ..config()
+        },
+    )?;
+
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            merged_locals: set! {"release-v1", "release-tmp-1"},
+            merged_remotes: set! {
+                "refs/remotes/origin/release-v1",
+                "refs/remotes/origin/release-tmp-1"
+            },
+            ..Default::default()
+        },
+    );
+    Ok(())
+}
@@ -0,0 +1,171 @@
+mod fixture;
+
+use std::convert::TryFrom;
+
+use anyhow::Result;
+use git2::Repository;
+
+use git_trim::args::DeleteFilter;
+use git_trim::{
+    get_merged_or_gone, get_push_ref_on_remote, get_push_remote_ref, Config, Git, MergedOrGone,
+    RefOnRemote,
+};
+
+use fixture::{rc, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin --bare
+
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+            git config remote.pushdefault origin
+
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+            git push -u origin master
+
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin feature
+
+            git checkout master
+            git merge feature
+            git push origin master
+        EOF
+        "#,
+    )
+}
+
+fn config() -> Config<'static> {
+    Config {
+        bases: vec!["master"],
+        protected_branches: set! {},
+        filter: DeleteFilter::all(),
+        detach: true,
+        stale_after: None,
+        forge: None,
+    }
+}
+
+#[test]
+fn test_push_default_matching() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config push.default matching
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    assert_eq!(
+        get_push_ref_on_remote(&git.repo, &git.config, "feature")?,
+        Some(RefOnRemote {
+            remote_name: "origin".to_string(),
+            refname: "refs/heads/feature".to_string(),
+        }),
+    );
+    assert_eq!(
+        get_push_remote_ref(&git.repo, &git.config, "feature")?,
+        Some("refs/remotes/origin/feature".to_string()),
+    );
+
+    let branches = get_merged_or_gone(&git, &config())?;
+
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            merged_locals: set! {"feature"},
+            merged_remotes: set! {"refs/remotes/origin/feature"},
+            ..Default::default()
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_push_default_nothing() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config push.default nothing
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    assert_eq!(
+        get_push_ref_on_remote(&git.repo, &git.config, "feature")?,
+        None
+    );
+    assert_eq!(
+        get_push_remote_ref(&git.repo, &git.config, "feature")?,
+        None
+    );
+
+    // The remote ref is still deleted as the fetch upstream.
+    let branches = get_merged_or_gone(&git, &config())?;
+
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            merged_locals: set! {"feature"},
+            merged_remotes: set! {"refs/remotes/origin/feature"},
+            ..Default::default()
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_push_default_current() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config push.default current
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    assert_eq!(
+        get_push_ref_on_remote(&git.repo, &git.config, "feature")?,
+        Some(RefOnRemote {
+            remote_name: "origin".to_string(),
+            refname: "refs/heads/feature".to_string(),
+        }),
+    );
+    assert_eq!(
+        get_push_remote_ref(&git.repo, &git.config, "feature")?,
+        Some("refs/remotes/origin/feature".to_string()),
+    );
+    Ok(())
+}
+
+#[test]
+fn test_push_default_unknown() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    let repo = Repository::open(guard.working_directory())?;
+    // Git itself refuses to run with it, so it is set after the fixture.
+    repo.config()?.set_str("push.default", "unknown")?;
+    let git = Git::try_from(repo)?;
+    match get_push_ref_on_remote(&git.repo, &git.config, "feature") {
+        Err(git_trim::Error::Config { key, .. }) => assert_eq!(key, "push.default"),
+        result => panic!(
+            "push.default=unknown should be an invalid config: {:?}",
+            result
+        ),
+    }
+    Ok(())
+}
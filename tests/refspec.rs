@@ -0,0 +1,139 @@
+mod fixture;
+
+use std::convert::TryFrom;
+
+use anyhow::Result;
+use git2::Repository;
+
+use git_trim::args::DeleteFilter;
+use git_trim::{get_merged_or_gone, Config, Git, MergedOrGone};
+
+use fixture::{rc, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin
+        origin <<EOF
+            git config user.name "Origin Test"
+            git config user.email "origin@test"
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+
+            git checkout -b contribution
+            touch contribution-patch
+            git add contribution-patch
+            git commit -m "Contribution patch"
+            git update-ref refs/pull/1/head contribution
+            git checkout master
+            git branch -D contribution
+        EOF
+
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+            git config remote.pushdefault origin
+            git config push.default simple
+
+            git config --unset-all remote.origin.fetch
+            git config --add remote.origin.fetch '+refs/pull/*/head:refs/remotes/origin/pr/*'
+            git config --add remote.origin.fetch '+refs/heads/*:refs/remotes/origin/*'
+            git fetch origin
+        EOF
+        "#,
+    )
+}
+
+fn config() -> Config<'static> {
+    Config {
+        bases: vec!["master"],
+        protected_branches: set! {},
+        filter: DeleteFilter::all(),
+        detach: true,
+        stale_after: None,
+        forge: None,
+    }
+}
+
+#[test]
+fn test_mid_path_wildcard_and_multiple_refspecs() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin feature
+
+            git checkout -b pr-1 origin/pr/1
+            git checkout master
+        EOF
+
+        origin <<EOF
+            git merge feature
+            git merge refs/pull/1/head
+        EOF
+
+        local <<EOF
+            git fetch origin
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(&git, &config())?;
+
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            merged_locals: set! {"feature", "pr-1"},
+            merged_remotes: set! {"refs/remotes/origin/feature", "refs/remotes/origin/pr/1"},
+            ..Default::default()
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_negative_refspec() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b secret/feature
+            touch secret-patch
+            git add secret-patch
+            git commit -m "Secret patch"
+            git push -u origin secret/feature
+            git checkout master
+
+            git config --add remote.origin.fetch '^refs/heads/secret/*'
+        EOF
+
+        origin <<EOF
+            git merge secret/feature
+        EOF
+
+        local <<EOF
+            git fetch origin
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(&git, &config())?;
+
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            merged_locals: set! {"secret/feature"},
+            orphaned_remotes: set! {"refs/remotes/origin/secret/feature"},
+            ..Default::default()
+        },
+    );
+    Ok(())
+}
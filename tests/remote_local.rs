@@ -0,0 +1,138 @@
+mod fixture;
+
+use std::convert::TryFrom;
+
+use anyhow::Result;
+use git2::{BranchType, Repository};
+
+use git_trim::args::DeleteFilter;
+use git_trim::{
+    delete_local_branches, delete_remote_refs_locally, get_merged_or_gone, Config, Git,
+    MergedOrGone,
+};
+
+use fixture::{rc, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin --bare
+
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+            git config remote.pushdefault origin
+            git config push.default simple
+
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+            git push -u origin master
+
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin feature
+
+            git checkout master
+            git merge feature
+            git push origin master
+        EOF
+        "#,
+    )
+}
+
+fn config() -> Config<'static> {
+    Config {
+        bases: vec!["master"],
+        protected_branches: set! {},
+        filter: DeleteFilter::all(),
+        detach: true,
+        stale_after: None,
+        forge: None,
+    }
+}
+
+#[test]
+fn test_merged_remote_local() -> Result<()> {
+    let guard = fixture().prepare("local", r#""#)?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(
+        &git,
+        &Config {
+            filter: "merged-local,merged-remote-local:origin".parse()?,
+            ..config()
+        },
+    )?;
+
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            merged_locals: set! {"feature"},
+            merged_remotes: set! {"refs/remotes/origin/feature"},
+            local_only: set! {"refs/remotes/origin/feature"},
+            ..Default::default()
+        },
+    );
+    assert!(branches.to_delete.remotes().is_empty());
+    assert_eq!(
+        branches.to_delete.local_only_remotes(),
+        vec!["refs/remotes/origin/feature"],
+    );
+
+    delete_remote_refs_locally(&git.repo, &branches.to_delete.local_only_remotes(), false)
+        .into_result()?;
+    delete_local_branches(&git.repo, &branches.to_delete.locals(), false)?;
+    assert!(git
+        .repo
+        .find_branch("origin/feature", BranchType::Remote)
+        .is_err());
+    let origin = Repository::open(guard.working_directory().join("../origin"))?;
+    assert!(origin.find_branch("feature", BranchType::Local).is_ok());
+    Ok(())
+}
+
+#[test]
+fn test_remote_local_takes_precedence() -> Result<()> {
+    let guard = fixture().prepare("local", r#""#)?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(
+        &git,
+        &Config {
+            filter: "all,merged-remote-local:origin".parse()?,
+            ..config()
+        },
+    )?;
+
+    assert!(branches.to_delete.remotes().is_empty());
+    assert_eq!(
+        branches.to_delete.local_only_remotes(),
+        vec!["refs/remotes/origin/feature"],
+    );
+    Ok(())
+}
+
+#[test]
+fn test_remote_local_of_another_remote() -> Result<()> {
+    let guard = fixture().prepare("local", r#""#)?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(
+        &git,
+        &Config {
+            filter: "all,merged-remote-local:upstream".parse()?,
+            ..config()
+        },
+    )?;
+
+    assert_eq!(
+        branches.to_delete.remotes(),
+        vec!["refs/remotes/origin/feature"],
+    );
+    assert!(branches.to_delete.local_only_remotes().is_empty());
+    Ok(())
+}
@@ -0,0 +1,132 @@
+mod fixture;
+
+use std::convert::TryFrom;
+
+use anyhow::Result;
+use git2::Repository;
+
+use git_trim::args::DeleteFilter;
+use git_trim::report::{RefReport, Status};
+use git_trim::{get_merged_or_gone, Config, Git};
+
+use fixture::{rc, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin
+        origin <<EOF
+            git config user.name "Origin Test"
+            git config user.email "origin@test"
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+        EOF
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+            git config remote.pushdefault origin
+            git config push.default simple
+        EOF
+        "#,
+    )
+}
+
+fn config() -> Config<'static> {
+    Config {
+        bases: vec!["master"],
+        protected_branches: set! {},
+        filter: DeleteFilter::merged(),
+        detach: true,
+        stale_after: None,
+        forge: None,
+    }
+}
+
+#[test]
+fn test_report() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b merged
+            touch merged
+            git add merged
+            git commit -m "Merged patch"
+            git push -u origin merged
+
+            git checkout master
+            git checkout -b rejected
+            touch rejected
+            git add rejected
+            git commit -m "Rejected patch"
+            git push -u origin rejected
+            git checkout master
+        EOF
+
+        origin <<EOF
+            git merge merged
+            git branch -D rejected
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(&git, &config())?;
+    let report = branches.report(&git.repo)?;
+
+    let find = |reports: &[RefReport], refname: &str| -> Option<(Status, Option<String>)> {
+        reports
+            .iter()
+            .find(|r| r.refname == refname)
+            .map(|r| (r.status, r.base.clone()))
+    };
+
+    let base = Some("refs/remotes/origin/master".to_string());
+    assert_eq!(
+        find(&report.local_branches, "refs/heads/merged"),
+        Some((Status::MergedLocal, base.clone())),
+    );
+    // gone branches are classified, but the default filter doesn't delete them.
+    assert_eq!(
+        find(&report.local_branches, "refs/heads/rejected"),
+        Some((Status::Remain, base.clone())),
+    );
+    assert_eq!(
+        find(&report.local_branches, "refs/heads/master"),
+        Some((Status::Remain, None)),
+    );
+    assert_eq!(
+        find(&report.remote_refs, "refs/remotes/origin/merged"),
+        Some((Status::MergedRemote, base)),
+    );
+
+    let merged = report
+        .local_branches
+        .iter()
+        .find(|r| r.name == "merged")
+        .unwrap();
+    assert_eq!(merged.fetch.as_deref(), Some("refs/remotes/origin/merged"));
+    assert!(merged.reason.is_some());
+
+    let json = serde_json::to_value(&report)?;
+    assert_eq!(json["local_branches"][0]["status"], "remain");
+    Ok(())
+}
+
+#[test]
+fn test_status_is_displayed_as_in_json() -> Result<()> {
+    for status in &[
+        Status::MergedLocal,
+        Status::OrphanedRemote,
+        Status::KeptBack,
+    ] {
+        assert_eq!(
+            serde_json::to_value(status)?,
+            serde_json::Value::String(status.to_string())
+        );
+    }
+    assert_eq!(Status::OrphanedRemote.to_string(), "orphaned-remote");
+    Ok(())
+}
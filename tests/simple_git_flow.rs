@@ -40,8 +40,11 @@ fn config() -> Config<'static> {
     Config {
         bases: vec!["develop", "master"],
         protected_branches: set! {},
-        filter: DeleteFilter::all(),
+        // Rejected branches have commits that are never merged.
+        filter: "all,gone-unpushed-local".parse::<DeleteFilter>().unwrap(),
         detach: true,
+        stale_after: None,
+        forge: None,
     }
 }
 
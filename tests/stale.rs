@@ -0,0 +1,116 @@
+mod fixture;
+
+use std::convert::TryFrom;
+
+use anyhow::Result;
+use git2::Repository;
+
+use git_trim::args::DeleteFilter;
+use git_trim::{get_merged_or_gone, Config, Git, MergedOrGone};
+
+use fixture::{rc, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin --bare
+
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+            git config remote.pushdefault origin
+            git config push.default simple
+
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+            git push -u origin master
+        EOF
+        "#,
+    )
+}
+
+fn config() -> Config<'static> {
+    Config {
+        bases: vec!["master"],
+        protected_branches: set! {},
+        filter: DeleteFilter::all(),
+        detach: true,
+        stale_after: Some(30),
+        forge: None,
+    }
+}
+
+#[test]
+fn test_stale() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b abandoned
+            touch abandoned
+            git add abandoned
+            GIT_COMMITTER_DATE="2000-01-01T00:00:00" git commit -m "Abandoned patch"
+            git push -u origin abandoned
+
+            git checkout master
+            git checkout -b untracked
+            touch untracked
+            git add untracked
+            GIT_COMMITTER_DATE="2000-01-01T00:00:00" git commit -m "Untracked patch"
+
+            git checkout master
+            git checkout -b wip
+            touch wip
+            git add wip
+            git commit -m "Work in progress"
+            git push -u origin wip
+
+            git checkout master
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(&git, &config())?;
+
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            stale_locals: set! {"abandoned"},
+            stale_remotes: set! {"refs/remotes/origin/abandoned"},
+            ..Default::default()
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_stale_is_not_detected_by_default() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout -b abandoned
+            touch abandoned
+            git add abandoned
+            GIT_COMMITTER_DATE="2000-01-01T00:00:00" git commit -m "Abandoned patch"
+            git push -u origin abandoned
+            git checkout master
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(
+        &git,
+        &Config {
+            stale_after: None,
+            ..config()
+        },
+    )?;
+
+    assert_eq!(branches.to_delete, MergedOrGone::default());
+    Ok(())
+}
@@ -0,0 +1,139 @@
+mod fixture;
+
+use std::convert::TryFrom;
+
+use anyhow::Result;
+use git2::Repository;
+
+use git_trim::args::{CommaSeparatedSet, DeleteFilter};
+use git_trim::batch::{discover_submodules, submodule_config};
+use git_trim::{config, get_merged_or_gone, Config, Git, MergedOrGone};
+
+use fixture::{rc, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin --bare
+        git init submodule-origin --bare
+
+        git clone submodule-origin submodule-work
+        within submodule-work <<EOF
+            git config user.name "Submodule Test"
+            git config user.email "submodule@test"
+
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+            git push -u origin master
+
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin feature
+
+            git checkout master
+            git merge feature
+            git push origin master
+        EOF
+
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+
+            git -c protocol.file.allow=always submodule add ../submodule-origin sub
+            git -c protocol.file.allow=always submodule add ../submodule-origin unused
+            git commit -m "Add submodules"
+            git push -u origin master
+            git submodule deinit unused
+
+            cd sub
+            git checkout -b feature origin/feature
+        EOF
+        "#,
+    )
+}
+
+fn config() -> Config<'static> {
+    Config {
+        bases: vec!["master"],
+        protected_branches: set! {},
+        filter: DeleteFilter::all(),
+        detach: true,
+        stale_after: None,
+        forge: None,
+    }
+}
+
+#[test]
+fn test_discover_initialized_submodules() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    let repo = Repository::open(guard.working_directory())?;
+    let submodules = discover_submodules(&repo)?;
+
+    assert_eq!(
+        submodules,
+        vec![(
+            guard.working_directory().join("sub"),
+            guard.working_directory(),
+        )]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_submodule_config_falls_back_to_superproject() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config trim.bases develop
+            git config trim.protected release
+            git config --add trim.protected staging
+            git -C sub config trim.protected hotfix
+        EOF
+        "#,
+    )?;
+
+    let superproject = Repository::open(guard.working_directory())?;
+    let submodule = Repository::open(guard.working_directory().join("sub"))?;
+    let config = submodule_config(&submodule)?;
+    let fallback = superproject.config()?.snapshot()?;
+
+    let bases = config::get::<CommaSeparatedSet<String>>(&config, "trim.bases")
+        .with_fallback(Some(&fallback))
+        .parse_flatten()?
+        .expect("set in the superproject")
+        .unwrap();
+    assert_eq!(*bases, vec!["develop"]);
+    // Multi-valued keys are not merged with the superproject's.
+    let protected = config::get::<CommaSeparatedSet<String>>(&config, "trim.protected")
+        .with_fallback(Some(&fallback))
+        .parse_flatten()?
+        .expect("set in the submodule")
+        .unwrap();
+    assert_eq!(*protected, vec!["hotfix"]);
+    Ok(())
+}
+
+#[test]
+fn test_trim_submodule() -> Result<()> {
+    let guard = fixture().prepare("local", "")?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory().join("sub"))?)?;
+    let branches = get_merged_or_gone(&git, &config())?;
+
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            merged_locals: set! {"feature"},
+            merged_remotes: set! {"refs/remotes/origin/feature"},
+            ..Default::default()
+        },
+    );
+    assert!(branches.kept_back.is_empty());
+    Ok(())
+}
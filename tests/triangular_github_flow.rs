@@ -53,8 +53,11 @@ fn config() -> Config<'static> {
     Config {
         bases: vec!["master"],
         protected_branches: set! {},
-        filter: DeleteFilter::all(),
+        // Rejected branches have commits that are never merged.
+        filter: "all,gone-unpushed-local".parse::<DeleteFilter>().unwrap(),
         detach: true,
+        stale_after: None,
+        forge: None,
     }
 }
 
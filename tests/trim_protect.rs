@@ -0,0 +1,136 @@
+mod fixture;
+
+use std::convert::TryFrom;
+
+use anyhow::Result;
+use git2::Repository;
+
+use git_trim::args::DeleteFilter;
+use git_trim::{get_merged_or_gone, Config, Git, MergedOrGone};
+
+use fixture::{rc, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin --bare
+
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+            git config remote.pushdefault origin
+            git config push.default simple
+
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+            git push -u origin master
+
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin feature
+
+            git checkout master
+            git merge feature
+            git push origin master
+        EOF
+        "#,
+    )
+}
+
+fn config() -> Config<'static> {
+    Config {
+        bases: vec!["master"],
+        protected_branches: set! {},
+        filter: DeleteFilter::all(),
+        detach: true,
+        stale_after: None,
+        forge: None,
+    }
+}
+
+#[test]
+fn test_trim_protect_config() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config branch.feature.trimProtect true
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(&git, &config())?;
+
+    assert_eq!(branches.to_delete, MergedOrGone::default());
+    assert_eq!(
+        branches.kept_back.get("feature").map(String::as_str),
+        Some("Merged local but kept back because of 'git config branch.feature.trimProtect'"),
+    );
+    assert_eq!(
+        branches
+            .kept_back
+            .get("refs/remotes/origin/feature")
+            .map(String::as_str),
+        Some("Merged remotes but kept back because of 'git config branch.feature.trimProtect'"),
+    );
+    Ok(())
+}
+
+#[test]
+fn test_trim_protect_config_false() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git config branch.feature.trimProtect false
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(&git, &config())?;
+
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            merged_locals: set! {"feature"},
+            merged_remotes: set! {"refs/remotes/origin/feature"},
+            ..Default::default()
+        },
+    );
+    Ok(())
+}
+
+#[test]
+fn test_trim_protect_note() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git notes --ref=trim-protect add -m "Keep it for the release notes" feature
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(&git, &config())?;
+
+    assert_eq!(branches.to_delete, MergedOrGone::default());
+    assert_eq!(
+        branches.kept_back.get("feature").map(String::as_str),
+        Some("Merged local but kept back because of the note in refs/notes/trim-protect"),
+    );
+    assert_eq!(
+        branches
+            .kept_back
+            .get("refs/remotes/origin/feature")
+            .map(String::as_str),
+        Some("Merged remotes but kept back because of the note in refs/notes/trim-protect"),
+    );
+    Ok(())
+}
@@ -0,0 +1,138 @@
+mod fixture;
+
+use std::convert::TryFrom;
+
+use anyhow::Result;
+use git2::Repository;
+
+use git_trim::args::DeleteFilter;
+use git_trim::{get_merged_or_gone, Config, Git, MergedOrGone};
+
+use fixture::{rc, Fixture};
+
+fn fixture() -> Fixture {
+    rc().append_fixture_trace(
+        r#"
+        git init origin --bare
+
+        git clone origin local
+        local <<EOF
+            git config user.name "Local Test"
+            git config user.email "local@test"
+            git config remote.pushdefault origin
+            git config push.default simple
+
+            echo "Hello World!" > README.md
+            git add README.md
+            git commit -m "Initial commit"
+            git push -u origin master
+
+            git checkout -b feature
+            touch awesome-patch
+            git add awesome-patch
+            git commit -m "Awesome patch"
+            git push -u origin feature
+            git checkout master
+        EOF
+        "#,
+    )
+}
+
+fn config(filter: DeleteFilter) -> Config<'static> {
+    Config {
+        bases: vec!["master"],
+        protected_branches: set! {},
+        filter,
+        detach: true,
+        stale_after: None,
+        forge: None,
+    }
+}
+
+#[test]
+fn test_keep_gone_with_unpushed_work() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        local <<EOF
+            git checkout feature
+            touch another-patch
+            git add another-patch
+            git commit -m "Another patch"
+            touch yet-another-patch
+            git add yet-another-patch
+            git commit -m "Yet another patch"
+            git checkout master
+        EOF
+        origin <<EOF
+            git branch -D feature
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(&git, &config(DeleteFilter::all()))?;
+
+    assert_eq!(branches.to_delete, MergedOrGone::default());
+    assert_eq!(branches.unpushed_commits.get("feature"), Some(&3));
+    assert_eq!(
+        branches.kept_back.get("feature").map(String::as_str),
+        Some("Gone local but kept back because it has 3 unpushed commit(s)"),
+    );
+    Ok(())
+}
+
+#[test]
+fn test_delete_gone_with_unpushed_work_explicitly() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        origin <<EOF
+            git branch -D feature
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let filter = "gone-unpushed-local".parse::<DeleteFilter>().unwrap();
+    let branches = get_merged_or_gone(&git, &config(filter))?;
+
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            gone_locals: set! {"feature"},
+            ..Default::default()
+        },
+    );
+    assert_eq!(branches.unpushed_commits.get("feature"), Some(&1));
+    Ok(())
+}
+
+#[test]
+fn test_delete_gone_without_unpushed_work() -> Result<()> {
+    let guard = fixture().prepare(
+        "local",
+        r#"
+        git clone origin colleague
+        within colleague <<EOF
+            git checkout feature
+            git push origin feature:refs/heads/renamed
+            git push origin --delete feature
+        EOF
+        "#,
+    )?;
+
+    let git = Git::try_from(Repository::open(guard.working_directory())?)?;
+    let branches = get_merged_or_gone(&git, &config(DeleteFilter::all()))?;
+
+    assert_eq!(
+        branches.to_delete,
+        MergedOrGone {
+            gone_locals: set! {"feature"},
+            ..Default::default()
+        },
+    );
+    assert!(branches.unpushed_commits.is_empty());
+    assert!(branches.kept_back.is_empty());
+    Ok(())
+}